@@ -0,0 +1,157 @@
+extern crate encage_build;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process;
+use encage_build::config::Package;
+use encage_build::context::Context;
+use encage_build::console::{DefaultConsole, JsonConsole, LogLevel};
+use encage_build::fs::{OsFilesystem, SubFilesystem};
+use encage_build::index::PackageIndex;
+use encage_build::lock::{self, Lockfile, LockMode};
+use encage_build::plugins::Registry;
+use encage_build::plugins::handlebars::HandlebarsPlugin;
+use encage_build::schedule::BuildOptions;
+use encage_build::work::{self, Workspace};
+use encage_build::parse;
+
+fn usage() -> ! {
+	let _ = writeln!(io::stderr(), "usage: encage-build plan <recipe.toml>");
+	let _ = writeln!(io::stderr(), "       encage-build build [-j JOBS] [--keep-going] [--locked] [--workspace DIR] [--json] [-v | -q] <recipe.toml> [IMAGE...]");
+	process::exit(2)
+}
+
+fn plan(path: &str) -> Result<(), Box<Error>> {
+	let mut plugins = Registry::new();
+	plugins.register_builtins();
+
+	let recipe = try!(parse::parse(&mut try!(File::open(path)), &plugins));
+
+	let mut context = Context::new(DefaultConsole::default());
+	context.set_lockfile(try!(Lockfile::load(lock_path(Path::new(path)))), LockMode::Update);
+	context.load_index(try!(PackageIndex::from_search_path()));
+	try!(context.load_recipe(&plugins, recipe));
+	try!(context.resolve_dependencies(&plugins));
+
+	let stdout = io::stdout();
+	try!(context.write_plan(&mut stdout.lock()));
+
+	Ok(())
+}
+
+/// Builds the named images of a recipe, or all of them.
+fn build(args: &[String]) -> Result<(), Box<Error>> {
+	let mut options = BuildOptions::default();
+	let mut lock_mode = LockMode::Update;
+	let mut workspace = PathBuf::from(work::DEFAULT_ROOT);
+	let mut verbosity = LogLevel::Note;
+	let mut json = false;
+	let mut free = Vec::new();
+
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match &arg[..] {
+			"-j" | "--jobs" => options.jobs = args.next().and_then(|j| j.parse().ok()).unwrap_or_else(|| usage()),
+			"-k" | "--keep-going" => options.keep_going = true,
+			"--locked" => lock_mode = LockMode::Locked,
+			"-w" | "--workspace" => workspace = args.next().map(PathBuf::from).unwrap_or_else(|| usage()),
+			"--json" => json = true,
+			"-v" | "--verbose" => verbosity = if verbosity < LogLevel::Debug { LogLevel::Debug } else { LogLevel::Trace },
+			"-q" | "--quiet" => verbosity = LogLevel::Error,
+			jobs if jobs.starts_with("-j") => options.jobs = jobs[2..].parse().ok().unwrap_or_else(|| usage()),
+			option if option.starts_with('-') => usage(),
+			_ => free.push(&arg[..]),
+		}
+	}
+
+	let (path, images) = match free.split_first() {
+		Some((path, images)) => (Path::new(path), images),
+		None => usage(),
+	};
+	let workspace = try!(env::current_dir()).join(workspace);
+
+	let mut plugins = Registry::new();
+	plugins.register_builtins_with(HandlebarsPlugin::new().workspace(&workspace));
+
+	let recipe = try!(parse::parse(&mut try!(File::open(path)), &plugins));
+	let targets = if images.is_empty() {
+		recipe.images.iter().map(|image| image.package.clone()).collect()
+	} else {
+		let mut targets = Vec::new();
+		for name in images {
+			let name = try!(recipe.package.absolute_name(name)).into_owned();
+			match recipe.images.iter().find(|image| image.package.name == name) {
+				Some(image) => targets.push(image.package.clone()),
+				None => return Err(format!("{} has no image named {}", path.display(), name).into()),
+			}
+		}
+		targets
+	};
+
+	let lockfile_path = lock_path(path);
+	let lockfile = try!(Lockfile::load(&lockfile_path));
+
+	let mut context = Context::new(DefaultConsole::new(verbosity));
+	if json {
+		context.console = Box::new(JsonConsole::new(io::stdout(), verbosity));
+	}
+	context.set_lockfile(lockfile.clone(), lock_mode);
+	context.load_index(try!(PackageIndex::from_search_path()));
+	try!(context.load_recipe(&plugins, recipe));
+	let result = match context.resolve_dependencies(&plugins) {
+		Ok(()) => build_targets(&mut context, &targets, workspace, &options, path),
+		Err(e) => Err(e.into()),
+	};
+
+	// Versions and hashes pinned so far are kept even if the build failed.
+	if context.lockfile() != lockfile {
+		try!(context.lockfile().save(&lockfile_path));
+	}
+
+	result
+}
+
+fn build_targets(context: &mut Context, targets: &[Package], workspace: PathBuf, options: &BuildOptions, path: &Path) -> Result<(), Box<Error>> {
+	let workspace = Workspace::new(workspace);
+	let filesystem = OsFilesystem;
+	let sources = SubFilesystem::new(&filesystem, path.parent().unwrap_or(Path::new(".")));
+	let mut failed = None;
+	for target in targets {
+		if let Err(e) = context.build(target, &workspace, options, &sources) {
+			if !options.keep_going {
+				return Err(e.into())
+			}
+
+			let _ = writeln!(io::stderr(), "encage-build: {}", e);
+			failed = Some(target);
+		}
+	}
+
+	match failed {
+		Some(target) => Err(format!("failed to build {}", target.name).into()),
+		None => Ok(()),
+	}
+}
+
+/// The lockfile kept next to a recipe.
+fn lock_path(recipe: &Path) -> PathBuf {
+	recipe.parent().unwrap_or(Path::new(".")).join(lock::LOCKFILE_NAME)
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+
+	let result = match args.first().map(|s| &s[..]) {
+		Some("plan") if args.len() == 2 => plan(&args[1]),
+		Some("build") => build(&args[1..]),
+		_ => usage(),
+	};
+
+	if let Err(e) = result {
+		let _ = writeln!(io::stderr(), "encage-build: {}", e);
+		process::exit(1);
+	}
+}
@@ -1,79 +1,113 @@
-use ::{StaticIdDef, StaticId, Id};
+use Id;
 use config::Package;
+use phase::Phase;
+use error::Result;
 use work::Workspace;
+use console::Console;
 use std::fmt;
-use filesystem::FilesystemObject;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use fs::Filesystem;
+use lock::{Lockfile, LockMode};
 
-pub type BuildClass = StaticId;
+pub type BuildClass = Phase;
 
-pub trait BuildItem: fmt::Display + fmt::Debug {
+pub trait BuildItem: fmt::Display + fmt::Debug + Send + Sync {
 	fn class(&self) -> Option<BuildClass> { None }
 
-	fn register_dependencies(&self, context: &mut BuildDependencyContext) -> Result<(), ()>;
-	fn build(&self, context: &mut BuildContext) -> Result<(), ()>;
+	fn register_dependencies(&self, context: &mut BuildDependencyContext) -> Result<()>;
+	fn build(&self, context: &mut BuildContext) -> Result<()>;
 }
 
 pub struct BuildContext<'a> {
 	pub package: &'a Package,
+	/// The images `package` is layered on, nearest first.
+	pub layers: &'a [Package],
 	pub workspace: &'a Workspace,
-	pub source_filesystem: &'a mut FilesystemObject,
+	pub console: &'a Console,
+	pub source_filesystem: &'a Filesystem,
+	pub lockfile: &'a Mutex<Lockfile>,
+	pub lock_mode: LockMode,
+}
+
+impl<'a> BuildContext<'a> {
+	/// Pins the hash of a fetched file, failing if it differs from the pinned one.
+	pub fn lock_file(&self, url: &str, hash: &str) -> Result<()> {
+		self.lockfile.lock().unwrap().lock_file(url, hash, self.lock_mode)
+	}
 }
 
 pub struct BuildDependencyContext<'a> {
 	pub package: &'a Package,
-	pub depends_on: &'a mut FnMut(&Package, BuildClass),
-	pub required_by: &'a mut FnMut(&Package, BuildClass),
+	pub depends_on: &'a mut FnMut(&Package, &BuildClass),
+	pub required_by: &'a mut FnMut(&Package, &BuildClass),
 }
 
 impl<'a> BuildDependencyContext<'a> {
-	pub fn depends_on(&mut self, p: &Package, b: BuildClass) {
+	pub fn depends_on(&mut self, p: &Package, b: &BuildClass) {
 		(self.depends_on)(p, b);
 	}
 
-	pub fn required_by(&mut self, p: &Package, b: BuildClass) {
+	pub fn required_by(&mut self, p: &Package, b: &BuildClass) {
 		(self.required_by)(p, b);
 	}
 }
 
 pub struct BuildItems {
 	build_items: Vec<(Id, Package, Box<BuildItem>)>,
+	by_id: HashMap<Id, usize>,
+	by_class: HashMap<Package, HashMap<BuildClass, Vec<usize>>>,
 }
 
 impl BuildItems {
 	pub fn new() -> Self {
 		BuildItems {
 			build_items: Vec::new(),
+			by_id: HashMap::new(),
+			by_class: HashMap::new(),
 		}
 	}
 
 	pub fn register_build_item(&mut self, id: Id, package: &Package, b: Box<BuildItem>) {
+		let index = self.build_items.len();
+		if let Some(class) = b.class() {
+			self.by_class.entry(package.clone()).or_insert_with(HashMap::new).entry(class).or_insert_with(Vec::new).push(index);
+		}
+		self.by_id.insert(id, index);
 		self.build_items.push((id, package.clone(), b));
 	}
 
-	pub fn dependencies_matching(&self, package: &Package, class: BuildClass) -> Vec<(Id, &BuildItem)> {
-		self.build_items.iter().filter_map(|&(id, ref build_package, ref item)| if build_package == package && item.class() == Some(class) {
-			Some((id, &**item))
-		} else {
-			None
-		}).collect()
+	pub fn dependencies_matching(&self, package: &Package, class: &BuildClass) -> Vec<(Id, &BuildItem)> {
+		match self.by_class.get(package).and_then(|classes| classes.get(class)) {
+			Some(indices) => indices.iter().map(|&i| {
+				let (id, _, ref item) = self.build_items[i];
+				(id, &**item)
+			}).collect(),
+			None => Vec::new(),
+		}
 	}
 
 	pub fn iter(&self) -> ::std::slice::Iter<(Id, Package, Box<BuildItem>)> {
 		self.build_items.iter()
 	}
 
+	pub fn len(&self) -> usize {
+		self.build_items.len()
+	}
+
 	pub fn get(&self, id: Id) -> Option<(&Package, &BuildItem)> {
-		self.build_items.iter().filter_map(|&(build_id, ref package, ref item)| if id == build_id {
-			Some((package, &**item))
-		} else {
-			None
-		}).next()
+		self.by_id.get(&id).map(|&i| {
+			let (_, ref package, ref item) = self.build_items[i];
+			(package, &**item)
+		})
 	}
 }
 
-pub static BUILD_CLASS_PRESTAGE: StaticIdDef = StaticIdDef::INIT;
-pub static BUILD_CLASS_STAGE: StaticIdDef = StaticIdDef::INIT;
-pub static BUILD_CLASS_IMAGE: StaticIdDef = StaticIdDef::INIT;
+impl fmt::Debug for BuildItems {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_map().entries(self.build_items.iter().map(|&(id, ref package, ref item)| (id, (&package.name, item.class(), item.to_string())))).finish()
+	}
+}
 
 #[derive(Debug)]
 pub struct NullBuildItem(String, Option<BuildClass>);
@@ -85,10 +119,10 @@ impl NullBuildItem {
 }
 
 impl BuildItem for NullBuildItem {
-	fn class(&self) -> Option<BuildClass> { self.1 }
+	fn class(&self) -> Option<BuildClass> { self.1.clone() }
 
-	fn register_dependencies(&self, _context: &mut BuildDependencyContext) -> Result<(), ()> { Ok(()) }
-	fn build(&self, _context: &mut BuildContext) -> Result<(), ()> { Ok(()) }
+	fn register_dependencies(&self, _context: &mut BuildDependencyContext) -> Result<()> { Ok(()) }
+	fn build(&self, _context: &mut BuildContext) -> Result<()> { Ok(()) }
 }
 
 impl fmt::Display for NullBuildItem {
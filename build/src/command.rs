@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::io::{self, Read, BufRead, BufReader};
+use crossbeam;
+use std::fmt;
+use serde_value::{Value, DeserializerError};
+use parse::ImageKind;
+use console::{Console, LogLevel, Event, EventKind, Stream};
+use error::{Error, Stage, Result};
+
+/// The network a container command can reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+	/// No network at all, the default.
+	None,
+	/// Only the container's own loopback interface.
+	Loopback,
+	/// The host's network.
+	Host,
+}
+
+impl Network {
+	pub fn from_str<S: AsRef<str>>(str: S) -> Option<Self> {
+		Some(match str.as_ref() {
+			"none" => Network::None,
+			"loopback" => Network::Loopback,
+			"host" => Network::Host,
+			_ => return None,
+		})
+	}
+
+	/// Parses the `network` key of an image or command.
+	pub fn parse(value: Value) -> ::std::result::Result<Self, DeserializerError> {
+		let network: String = try!(value.deserialize_into());
+		Network::from_str(&network).ok_or_else(|| DeserializerError::Syntax(format!("unknown network `{}`, expected none, loopback or host", network)))
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match *self {
+			Network::None => "none",
+			Network::Loopback => "loopback",
+			Network::Host => "host",
+		}
+	}
+}
+
+impl Default for Network {
+	fn default() -> Self {
+		Network::None
+	}
+}
+
+/// Resources a container command may use, enforced by `encage-run`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+	/// Memory in bytes.
+	pub memory: Option<u64>,
+	/// Cpu time as a number of cpus.
+	pub cpus: Option<f64>,
+	/// Number of processes and threads.
+	pub pids: Option<u64>,
+	/// Wall-clock time in seconds.
+	pub timeout: Option<u64>,
+}
+
+impl Limits {
+	/// Parses a table such as `{ memory = "2G", cpus = 2, pids = 512, timeout = "30m" }`.
+	pub fn parse(value: Value) -> ::std::result::Result<Limits, DeserializerError> {
+		let mut table: BTreeMap<String, Value> = try!(value.deserialize_into());
+		let limits = Limits {
+			memory: match table.remove("memory") {
+				Some(memory) => Some(try!(parse_scaled(memory, &[("K", 1 << 10), ("M", 1 << 20), ("G", 1 << 30)], "memory limit"))),
+				None => None,
+			},
+			cpus: match table.remove("cpus") {
+				Some(cpus) => {
+					let cpus: f64 = try!(cpus.deserialize_into());
+					if cpus > 0.0 {
+						Some(cpus)
+					} else {
+						return Err(DeserializerError::Syntax(format!("invalid cpu limit `{}`", cpus)))
+					}
+				},
+				None => None,
+			},
+			pids: match table.remove("pids") {
+				Some(pids) => Some(try!(parse_scaled(pids, &[], "process limit"))),
+				None => None,
+			},
+			timeout: match table.remove("timeout") {
+				Some(timeout) => Some(try!(parse_scaled(timeout, &[("s", 1), ("m", 60), ("h", 60 * 60)], "timeout"))),
+				None => None,
+			},
+		};
+
+		if let Some(key) = table.keys().next() {
+			return Err(DeserializerError::Syntax(format!("unknown limit `{}`, expected memory, cpus, pids or timeout", key)))
+		}
+
+		Ok(limits)
+	}
+
+	/// These limits, falling back to `defaults` for any that are unset.
+	pub fn or(&self, defaults: &Limits) -> Limits {
+		Limits {
+			memory: self.memory.or(defaults.memory),
+			cpus: self.cpus.or(defaults.cpus),
+			pids: self.pids.or(defaults.pids),
+			timeout: self.timeout.or(defaults.timeout),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		*self == Limits::default()
+	}
+
+	/// The `encage-run` options enforcing these limits.
+	pub fn args(&self) -> Vec<String> {
+		let mut args = Vec::new();
+		if let Some(memory) = self.memory {
+			args.push("--memory".into());
+			args.push(memory.to_string());
+		}
+		if let Some(cpus) = self.cpus {
+			args.push("--cpus".into());
+			args.push(cpus.to_string());
+		}
+		if let Some(pids) = self.pids {
+			args.push("--pids".into());
+			args.push(pids.to_string());
+		}
+		if let Some(timeout) = self.timeout {
+			args.push("--timeout".into());
+			args.push(timeout.to_string());
+		}
+		args
+	}
+}
+
+/// Parses a positive integer, or a string of one followed by one of `units`.
+fn parse_scaled(value: Value, units: &[(&str, u64)], what: &str) -> ::std::result::Result<u64, DeserializerError> {
+	let s = match value.clone().deserialize_into::<u64>() {
+		Ok(n) => n.to_string(),
+		Err(..) => try!(value.deserialize_into::<String>()),
+	};
+
+	let n = {
+		let (digits, scale) = units.iter().find(|&&(suffix, _)| s.ends_with(suffix))
+			.map(|&(suffix, scale)| (&s[..s.len() - suffix.len()], scale))
+			.unwrap_or((&s[..], 1));
+		digits.parse::<u64>().ok().and_then(|n| n.checked_mul(scale))
+	};
+
+	match n {
+		Some(n) if n > 0 => Ok(n),
+		_ => Err(DeserializerError::Syntax(format!("invalid {} `{}`", what, s))),
+	}
+}
+
+/// Where container commands find the files fetched for their image.
+pub const RESOURCE_PATH: &'static str = "/mnt/res";
+/// Where container commands find the image being built, like `$ENCAGE_TARGET`
+/// for host commands.
+pub const TARGET_PATH: &'static str = "/mnt/target";
+
+/// Where a command runs.
+pub struct Root<'a> {
+	/// The staging directory of the image being built, which receives every write.
+	pub stage: &'a Path,
+	/// The files fetched for the image, mounted at `RESOURCE_PATH` in containers.
+	pub resources: PathBuf,
+	/// Staging directories of the images this one is layered on, nearest first.
+	pub layers: Vec<PathBuf>,
+	/// Scratch space for the overlay, on the same filesystem as the stage.
+	pub overlay_work: PathBuf,
+	/// Ignored by host commands, which always use the host's network.
+	pub network: Network,
+	/// The hostname inside the container, if not encage-run's default.
+	pub hostname: Option<String>,
+	/// Capabilities kept beyond the runtime's default set, such as `SYS_PTRACE`.
+	pub capabilities: Vec<String>,
+	/// Keeps every capability and allows the syscalls the runtime otherwise
+	/// blocks, like mount, for bootstrap images.
+	pub privileged: bool,
+	/// Host devices exposed beyond the runtime's default set, such as `/dev/fuse`.
+	pub devices: Vec<String>,
+}
+
+/// A single command run while building an image.
+#[derive(Debug, Clone)]
+pub enum Command {
+	Shell(String),
+	Exec(Vec<String>),
+}
+
+impl Command {
+	/// Parses a single shell string, or a list of shell strings, argument
+	/// lists and `{ command = ..., limits = { ... }, network = ... }` tables,
+	/// along with the limits and network of each command.
+	pub fn parse_list(value: Value) -> ::std::result::Result<Vec<(Command, Limits, Option<Network>)>, DeserializerError> {
+		if let Ok(shell) = value.clone().deserialize_into::<String>() {
+			return Ok(vec![(Command::Shell(shell), Limits::default(), None)])
+		}
+
+		let commands: Vec<Value> = try!(value.deserialize_into());
+		commands.into_iter().map(|command| {
+			if let Ok(mut table) = command.clone().deserialize_into::<BTreeMap<String, Value>>() {
+				let limits = match table.remove("limits") {
+					Some(limits) => try!(Limits::parse(limits)),
+					None => Limits::default(),
+				};
+				let network = match table.remove("network") {
+					Some(network) => Some(try!(Network::parse(network))),
+					None => None,
+				};
+				let command = try!(table.remove("command").ok_or_else(|| DeserializerError::Syntax("a command table requires a `command`".into())));
+				if let Some(key) = table.keys().next() {
+					return Err(DeserializerError::Syntax(format!("unknown command key `{}`, expected command, limits or network", key)))
+				}
+
+				Command::parse(command).map(|command| (command, limits, network))
+			} else {
+				Command::parse(command).map(|command| (command, Limits::default(), None))
+			}
+		}).collect()
+	}
+
+	/// Parses a shell string or an argument list.
+	fn parse(value: Value) -> ::std::result::Result<Command, DeserializerError> {
+		if let Ok(shell) = value.clone().deserialize_into::<String>() {
+			Ok(Command::Shell(shell))
+		} else {
+			let args: Vec<String> = try!(value.deserialize_into());
+			if args.is_empty() {
+				Err(DeserializerError::Syntax("a command requires at least one argument".into()))
+			} else {
+				Ok(Command::Exec(args))
+			}
+		}
+	}
+
+	pub fn map_strings<E, F: FnMut(String) -> ::std::result::Result<String, E>>(self, mut f: F) -> ::std::result::Result<Self, E> {
+		Ok(match self {
+			Command::Shell(s) => Command::Shell(try!(f(s))),
+			Command::Exec(args) => Command::Exec(try!(args.into_iter().map(f).collect())),
+		})
+	}
+
+	pub fn args(&self) -> Vec<&str> {
+		match *self {
+			Command::Shell(ref s) => vec!["sh", "-ec", s],
+			Command::Exec(ref args) => args.iter().map(|s| &s[..]).collect(),
+		}
+	}
+
+	/// The `encage-run` arguments running the command inside `root`.
+	///
+	/// The stage is the container's root, and is also mounted at
+	/// `TARGET_PATH`, next to the fetched files at `RESOURCE_PATH`.
+	pub fn runtime_args(&self, root: &Root, limits: &Limits) -> Vec<OsString> {
+		let mut args: Vec<OsString> = vec!["exec".into(), "--network".into(), root.network.as_str().into()];
+		args.extend(limits.args().into_iter().map(OsString::from));
+		if let Some(ref hostname) = root.hostname {
+			args.push("--hostname".into());
+			args.push(hostname.into());
+		}
+		for capability in &root.capabilities {
+			args.push("--capability".into());
+			args.push(capability.into());
+		}
+		if root.privileged {
+			args.push("--privileged".into());
+		}
+		for device in &root.devices {
+			args.push("--device".into());
+			args.push(device.into());
+		}
+		for &(src, dest) in &[(root.resources.as_path(), RESOURCE_PATH), (root.stage, TARGET_PATH)] {
+			let mut mount = OsString::from(src);
+			mount.push(":");
+			mount.push(dest);
+			args.push("--mount".into());
+			args.push(mount);
+		}
+		if !root.layers.is_empty() {
+			for layer in &root.layers {
+				args.push("--lower".into());
+				args.push(layer.into());
+			}
+			args.push("--work".into());
+			args.push(root.overlay_work.clone().into());
+		}
+		args.push(root.stage.into());
+		args.extend(self.args().into_iter().map(OsString::from));
+		args
+	}
+
+	/// Runs the command against an image's staging directory.
+	///
+	/// Host commands run directly on the host with the stage as their working
+	/// directory and `$ENCAGE_TARGET`, while container commands run inside the
+	/// stage through `encage-run`, layered on top of the images it depends on
+	/// and within `limits`.
+	pub fn run(&self, kind: ImageKind, root: &Root, limits: &Limits, console: &Console) -> Result<()> {
+		let args = self.args();
+		let mut command = match kind {
+			ImageKind::Host => {
+				let mut command = process::Command::new(args[0]);
+				command.args(&args[1..])
+					.current_dir(root.stage)
+					.env("ENCAGE_TARGET", root.stage);
+				command
+			},
+			ImageKind::Container => {
+				let mut command = process::Command::new("encage-run");
+				command.args(&self.runtime_args(root, limits));
+				command
+			},
+		};
+
+		console.log(LogLevel::Debug, format_args!("{}{}", kind.log_prefix(), self));
+
+		let mut child = try!(command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+			.map_err(|e| Error::new(Stage::Build, e).with_message(format!("failed to run `{}`", args[0])))
+		);
+
+		let stdout = child.stdout.take().expect("stdout is piped");
+		let stderr = child.stderr.take().expect("stderr is piped");
+		let output = crossbeam::scope(|scope| {
+			let stderr = scope.spawn(move || forward_lines(stderr, Stream::Stderr, console));
+			forward_lines(stdout, Stream::Stdout, console).and_then(|_| stderr.join())
+		});
+
+		let (status, runtime_error) = try!(output.and_then(|runtime_error| child.wait().map(|status| (status, runtime_error)))
+			.map_err(|e| Error::new(Stage::Build, e).with_message(format!("failed to run `{}`", args[0])))
+		);
+		if status.success() {
+			Ok(())
+		} else {
+			Err(Error::status(Stage::Build, status).with_message(match runtime_error {
+				// Such as the limit that stopped the command.
+				Some(reason) => format!("`{}` failed: {}", self, reason),
+				None => format!("`{}` failed", self),
+			}))
+		}
+	}
+}
+
+/// Forwards each line as command output, returning the last error reported by `encage-run` itself.
+fn forward_lines<R: Read>(r: R, stream: Stream, console: &Console) -> io::Result<Option<String>> {
+	let mut runtime_error = None;
+	for line in BufReader::new(r).lines() {
+		let line = try!(line);
+		console.event(&Event::new(EventKind::CommandOutput {
+			stream: stream,
+			line: &line,
+		}));
+		if line.starts_with(RUNTIME_PREFIX) {
+			runtime_error = Some(line[RUNTIME_PREFIX.len()..].to_owned());
+		}
+	}
+
+	Ok(runtime_error)
+}
+
+const RUNTIME_PREFIX: &'static str = "encage-run: ";
+
+impl fmt::Display for Command {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Command::Shell(ref s) => write!(f, "sh -ec {:?}", s),
+			Command::Exec(ref args) => f.write_str(&args.join(" ")),
+		}
+	}
+}
@@ -1,5 +1,7 @@
 use std::borrow::Cow;
-use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use semver::{Version, VersionReq, ReqParseError};
+use error::{Error, Stage, Result};
 
 #[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct Package {
@@ -13,14 +15,49 @@ pub struct PackageQuery {
 	pub version_req: VersionReq,
 }
 
+impl PackageQuery {
+	/// Parses a `name@version_req` query. The version requirement is optional.
+	pub fn parse<S: AsRef<str>>(str: S) -> ::std::result::Result<Self, ReqParseError> {
+		let str = str.as_ref();
+		let (name, req) = match str.find('@') {
+			Some(at) => (&str[..at], &str[at + 1..]),
+			None => (str, "*"),
+		};
+
+		Ok(PackageQuery {
+			name: name.to_owned(),
+			version_req: try!(VersionReq::parse(req)),
+		})
+	}
+}
+
 impl Package {
-	pub fn absolute_name<'a, S: AsRef<str> + 'a>(&self, str: &'a S) -> Cow<'a, str> {
+	/// Resolves a package name relative to this one.
+	///
+	/// A leading `.` names a child of this package, and every further `.`
+	/// moves up one level, so from `mx.arcn.hello`, `.arch` is
+	/// `mx.arcn.hello.arch` and `..arch` is `mx.arcn.arch`.
+	pub fn absolute_name<'a, S: AsRef<str> + 'a>(&self, str: &'a S) -> Result<Cow<'a, str>> {
 		let str = str.as_ref();
-		if str.starts_with('.') {
-			Cow::Owned(format!("{}{}", self.name, str))
-		} else {
-			Cow::Borrowed(str)
+		let dots = str.chars().take_while(|&c| c == '.').count();
+		if dots == 0 {
+			return Ok(Cow::Borrowed(str))
 		}
+
+		let rest = &str[dots..];
+		if rest.is_empty() {
+			return Err(Error::message(Stage::Configure, format!("`{}` is not a valid package name", str)))
+		}
+
+		let mut base = &self.name[..];
+		for _ in 1..dots {
+			base = match base.rfind('.') {
+				Some(i) => &base[..i],
+				None => return Err(Error::message(Stage::Configure, format!("`{}` refers above the top-level namespace of {}", str, self.name))),
+			};
+		}
+
+		Ok(Cow::Owned(format!("{}.{}", base, rest)))
 	}
 
 	pub fn to_query(&self) -> PackageQuery {
@@ -30,10 +67,49 @@ impl Package {
 		}
 	}
 
-	pub fn into_absolute<S: AsRef<str>>(self, str: S) -> Self {
-		Package {
-			name: self.absolute_name(&str).into_owned(),
+	pub fn into_absolute<S: AsRef<str>>(self, str: S) -> Result<Self> {
+		Ok(Package {
+			name: try!(self.absolute_name(&str)).into_owned(),
 			version: self.version,
+		})
+	}
+}
+
+/// Resolves the package names used within a recipe.
+#[derive(Debug, Clone)]
+pub struct Namespace {
+	pub root: Package,
+	/// Short names for other packages, declared in `[package] aliases`.
+	pub aliases: BTreeMap<String, String>,
+}
+
+impl Namespace {
+	pub fn new(root: Package) -> Self {
+		Namespace {
+			root: root,
+			aliases: BTreeMap::new(),
 		}
 	}
+
+	/// Resolves a relative name, or a name whose first component is an alias.
+	pub fn resolve<S: AsRef<str>>(&self, name: S) -> Result<String> {
+		let name = name.as_ref();
+		let (head, tail) = match name.find('.') {
+			Some(0) => return self.root.absolute_name(&name).map(Cow::into_owned),
+			Some(i) => (&name[..i], &name[i..]),
+			None => (name, ""),
+		};
+
+		Ok(match self.aliases.get(head) {
+			Some(target) => format!("{}{}", target, tail),
+			None => name.to_owned(),
+		})
+	}
+
+	pub fn resolve_package<S: AsRef<str>>(&self, name: S) -> Result<Package> {
+		Ok(Package {
+			name: try!(self.resolve(name)),
+			version: self.root.version.clone(),
+		})
+	}
 }
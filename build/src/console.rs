@@ -1,6 +1,9 @@
+use std::io::Write;
+use std::sync::Mutex;
 use std::fmt;
+use error::Error;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
 pub enum LogLevel {
 	Error,
 	Note,
@@ -8,14 +11,282 @@ pub enum LogLevel {
 	Trace,
 }
 
-pub trait Console {
+impl LogLevel {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			LogLevel::Error => "error",
+			LogLevel::Note => "note",
+			LogLevel::Debug => "debug",
+			LogLevel::Trace => "trace",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+	Stdout,
+	Stderr,
+}
+
+/// Something that happened during a build.
+#[derive(Debug, Clone, Copy)]
+pub enum EventKind<'a> {
+	ItemStarted,
+	ItemFinished,
+	ItemFailed(&'a Error),
+	CommandOutput {
+		stream: Stream,
+		line: &'a str,
+	},
+	DownloadProgress {
+		url: &'a str,
+		received: u64,
+		total: Option<u64>,
+	},
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Event<'a> {
+	/// The build item the event came from, if any.
+	pub source: Option<&'a str>,
+	pub kind: EventKind<'a>,
+}
+
+impl<'a> Event<'a> {
+	pub fn new(kind: EventKind<'a>) -> Self {
+		Event {
+			source: None,
+			kind: kind,
+		}
+	}
+
+	pub fn level(&self) -> LogLevel {
+		match self.kind {
+			EventKind::ItemFailed(..) => LogLevel::Error,
+			EventKind::ItemStarted | EventKind::ItemFinished | EventKind::CommandOutput { .. } => LogLevel::Note,
+			EventKind::DownloadProgress { .. } => LogLevel::Debug,
+		}
+	}
+}
+
+impl<'a> fmt::Display for Event<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if let Some(source) = self.source {
+			try!(write!(f, "[{}] ", source));
+		}
+
+		match self.kind {
+			EventKind::ItemStarted => f.write_str("started"),
+			EventKind::ItemFinished => f.write_str("finished"),
+			EventKind::ItemFailed(e) => write!(f, "failed: {}", e),
+			EventKind::CommandOutput { line, .. } => f.write_str(line),
+			EventKind::DownloadProgress { url, received, total: Some(total) } => write!(f, "{}: {}/{} bytes", url, received, total),
+			EventKind::DownloadProgress { url, received, total: None } => write!(f, "{}: {} bytes", url, received),
+		}
+	}
+}
+
+pub trait Console: Send + Sync {
 	fn log(&self, level: LogLevel, args: fmt::Arguments);
+
+	fn event(&self, event: &Event) {
+		self.log(event.level(), format_args!("{}", event));
+	}
+}
+
+/// Human-readable output on stdout, filtered by verbosity.
+pub struct DefaultConsole {
+	pub verbosity: LogLevel,
 }
 
-pub struct DefaultConsole;
+impl DefaultConsole {
+	pub fn new(verbosity: LogLevel) -> Self {
+		DefaultConsole {
+			verbosity: verbosity,
+		}
+	}
+}
+
+impl Default for DefaultConsole {
+	fn default() -> Self {
+		DefaultConsole::new(LogLevel::Note)
+	}
+}
 
 impl Console for DefaultConsole {
 	fn log(&self, level: LogLevel, args: fmt::Arguments) {
-		println!("{:?}: {}", level, args);
+		if level <= self.verbosity {
+			println!("{}: {}", level.name(), args);
+		}
+	}
+
+	fn event(&self, event: &Event) {
+		if event.level() > self.verbosity {
+			return
+		}
+
+		match event.kind {
+			EventKind::CommandOutput { .. } => println!("{}", event),
+			_ => println!("{}: {}", event.level().name(), event),
+		}
+	}
+}
+
+/// One JSON object per line, for consumption by CI systems.
+pub struct JsonConsole<W> {
+	verbosity: LogLevel,
+	writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonConsole<W> {
+	pub fn new(writer: W, verbosity: LogLevel) -> Self {
+		JsonConsole {
+			verbosity: verbosity,
+			writer: Mutex::new(writer),
+		}
+	}
+
+	/// The writer, once nothing else will be logged to it.
+	pub fn into_inner(self) -> W {
+		self.writer.into_inner().unwrap()
+	}
+
+	fn write_line(&self, fields: &[(&str, JsonValue)]) {
+		let mut line = String::from("{");
+		for (i, &(key, ref value)) in fields.iter().enumerate() {
+			if i > 0 {
+				line.push(',');
+			}
+			json_string(&mut line, key);
+			line.push(':');
+			match *value {
+				JsonValue::String(ref s) => json_string(&mut line, s),
+				JsonValue::Unsigned(n) => line.push_str(&n.to_string()),
+				JsonValue::Signed(n) => line.push_str(&n.to_string()),
+				JsonValue::Null => line.push_str("null"),
+			}
+		}
+		line.push_str("}\n");
+
+		let mut writer = self.writer.lock().unwrap();
+		let _ = writer.write_all(line.as_bytes()).and_then(|_| writer.flush());
+	}
+}
+
+enum JsonValue {
+	String(String),
+	Unsigned(u64),
+	Signed(i64),
+	Null,
+}
+
+impl<'a> From<&'a str> for JsonValue {
+	fn from(s: &'a str) -> Self {
+		JsonValue::String(s.to_owned())
+	}
+}
+
+impl<'a> From<Option<&'a str>> for JsonValue {
+	fn from(s: Option<&'a str>) -> Self {
+		s.map(JsonValue::from).unwrap_or(JsonValue::Null)
+	}
+}
+
+fn json_string(out: &mut String, s: &str) {
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+}
+
+impl<W: Write + Send> Console for JsonConsole<W> {
+	fn log(&self, level: LogLevel, args: fmt::Arguments) {
+		if level <= self.verbosity {
+			self.write_line(&[
+				("event", "log".into()),
+				("level", level.name().into()),
+				("message", JsonValue::String(args.to_string())),
+			]);
+		}
+	}
+
+	fn event(&self, event: &Event) {
+		if event.level() > self.verbosity {
+			return
+		}
+
+		let source = ("source", event.source.into());
+		match event.kind {
+			EventKind::ItemStarted => self.write_line(&[("event", "item-started".into()), source]),
+			EventKind::ItemFinished => self.write_line(&[("event", "item-finished".into()), source]),
+			EventKind::ItemFailed(e) => self.write_line(&[
+				("event", "item-failed".into()),
+				source,
+				("error", JsonValue::String(e.to_string())),
+				("exit-status", e.exit_status().and_then(|s| s.code()).map(|c| JsonValue::Signed(c as i64)).unwrap_or(JsonValue::Null)),
+			]),
+			EventKind::CommandOutput { stream, line } => self.write_line(&[
+				("event", "output".into()),
+				source,
+				("stream", match stream { Stream::Stdout => "stdout", Stream::Stderr => "stderr" }.into()),
+				("line", line.into()),
+			]),
+			EventKind::DownloadProgress { url, received, total } => self.write_line(&[
+				("event", "download".into()),
+				source,
+				("url", url.into()),
+				("received", JsonValue::Unsigned(received)),
+				("total", total.map(JsonValue::Unsigned).unwrap_or(JsonValue::Null)),
+			]),
+		}
+	}
+}
+
+/// Attributes every message and event to the build item it came from, and
+/// keeps a copy of them for the item's log file.
+pub struct TaggedConsole<'a> {
+	inner: &'a Console,
+	tag: String,
+	log: Mutex<Vec<u8>>,
+}
+
+impl<'a> TaggedConsole<'a> {
+	pub fn new<S: Into<String>>(inner: &'a Console, tag: S) -> Self {
+		TaggedConsole {
+			inner: inner,
+			tag: tag.into(),
+			log: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Everything logged through this console, regardless of verbosity.
+	pub fn into_log(self) -> Vec<u8> {
+		self.log.into_inner().unwrap()
+	}
+}
+
+impl<'a> Console for TaggedConsole<'a> {
+	fn log(&self, level: LogLevel, args: fmt::Arguments) {
+		let _ = writeln!(self.log.lock().unwrap(), "{}: {}", level.name(), args);
+		self.inner.log(level, format_args!("[{}] {}", self.tag, args));
+	}
+
+	fn event(&self, event: &Event) {
+		let event = Event {
+			source: Some(event.source.unwrap_or(&self.tag)),
+			kind: event.kind,
+		};
+
+		let _ = writeln!(self.log.lock().unwrap(), "{}: {}", event.level().name(), Event { source: None, kind: event.kind });
+		self.inner.event(&event);
 	}
 }
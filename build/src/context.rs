@@ -1,29 +1,146 @@
 use Id;
 use std::fmt;
+use std::collections::{HashSet, HashMap, BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+use std::sync::{mpsc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::{cmp, mem};
+use crossbeam;
+use fs::Filesystem;
 use config::{Package, PackageQuery};
-use console::Console;
+use console::{Console, LogLevel, TaggedConsole, Event, EventKind};
 use dependencies::DependencyGraph;
 use build::{self, BuildItems, BuildItem};
+use error::{self, Error, Result};
+use phase::{self, PhaseRegistry};
+use work::Workspace;
+use index::PackageIndex;
+use lock::{Lockfile, LockMode};
+use parse::{self, ImageKind, ImageDesc, Recipe};
+use plugins::{Registry, ImageRequirementContext, ImageDependencyContext};
+use resolve::{Requirement, Resolver};
+use schedule::{Schedule, BuildOptions};
 
 pub struct Context {
 	pub console: Box<Console>,
-	pub packages: Vec<Package>,
+	/// Every package available to resolution, from the index or loaded recipes.
+	pub packages: BTreeSet<Package>,
 	pub dependency_graph: DependencyGraph,
 	pub build_items: BuildItems,
+	pub index: PackageIndex,
+	pub phases: PhaseRegistry,
+	images: BTreeMap<Package, ImageKind>,
+	layers: BTreeMap<Package, Vec<Package>>,
+	completed: HashSet<Id>,
+	lockfile: Mutex<Lockfile>,
+	lock_mode: LockMode,
+	requirements: Vec<Requirement>,
+	resolved: BTreeMap<String, Package>,
+	/// Loaded images whose dependencies haven't been configured yet.
+	pending: Vec<ImageDesc>,
 }
 
 impl Context {
 	pub fn new<C: Console + 'static>(console: C) -> Self {
 		Context {
 			console: Box::new(console),
-			packages: Vec::new(),
+			packages: BTreeSet::new(),
 			dependency_graph: DependencyGraph::new(),
 			build_items: BuildItems::new(),
+			index: PackageIndex::new(),
+			phases: {
+				let mut phases = PhaseRegistry::new();
+				phases.register_builtins();
+				phases
+			},
+			images: BTreeMap::new(),
+			layers: BTreeMap::new(),
+			completed: HashSet::new(),
+			lockfile: Mutex::new(Lockfile::new()),
+			lock_mode: LockMode::Update,
+			requirements: Vec::new(),
+			resolved: BTreeMap::new(),
+			pending: Vec::new(),
 		}
 	}
 
+	/// Pins resolved versions and fetched files in `lockfile`, failing
+	/// rather than changing it under `LockMode::Locked`.
+	pub fn set_lockfile(&mut self, lockfile: Lockfile, mode: LockMode) {
+		self.lockfile = Mutex::new(lockfile);
+		self.lock_mode = mode;
+	}
+
+	/// The lockfile, as updated by resolution and builds so far.
+	pub fn lockfile(&self) -> Lockfile {
+		self.lockfile.lock().unwrap().clone()
+	}
+
+	/// Finds the highest version of a package matching `query`.
 	pub fn query_package<'a>(&'a self, query: &PackageQuery) -> Option<&'a Package> {
-		self.packages.iter().find(|package| package.name == query.name && query.version_req.matches(&package.version))
+		self.packages.iter().filter(|package| package.name == query.name && query.version_req.matches(&package.version))
+			.max_by_key(|package| &package.version)
+	}
+
+	/// Makes every package in `index` available to queries, loading their
+	/// recipes once resolution picks them.
+	pub fn load_index(&mut self, index: PackageIndex) {
+		self.packages.extend(index.packages().cloned());
+		self.index = index;
+	}
+
+	pub fn register_image(&mut self, package: &Package, kind: ImageKind) {
+		self.packages.insert(package.clone());
+		self.images.insert(package.clone(), kind);
+	}
+
+	pub fn image_kind(&self, package: &Package) -> Option<ImageKind> {
+		self.images.get(package).cloned()
+	}
+
+	/// Records the images `package` is directly layered on.
+	pub fn register_layers(&mut self, package: &Package, depends: Vec<Package>) {
+		self.layers.insert(package.clone(), depends);
+	}
+
+	/// Every image `package` is layered on, directly or not, nearest first.
+	pub fn layers(&self, package: &Package) -> Vec<Package> {
+		layer_chain(&self.layers, package)
+	}
+
+	/// The version of a required package chosen by resolution.
+	pub fn resolved_package(&self, name: &str) -> Option<&Package> {
+		self.resolved.get(name)
+	}
+
+	/// Registers the images of a parsed recipe along with the packages they
+	/// require. Their build items are registered once those are resolved.
+	pub fn load_recipe(&mut self, registry: &Registry, recipe: Recipe) -> Result<()> {
+		registry.register_phases(&mut self.phases);
+		for decl in recipe.phases {
+			self.phases.declare(decl);
+		}
+
+		for image in recipe.images {
+			self.register_image(&image.package, image.kind);
+
+			let mut requirements = Vec::new();
+			registry.image_requirements(&mut ImageRequirementContext {
+				package: &image.package,
+				plugin_data: &image.plugin_data,
+				requirements: &mut requirements,
+			});
+			self.requirements.extend(requirements.into_iter().map(|query| Requirement {
+				query: query,
+				required_by: Some(image.package.clone()),
+			}));
+
+			self.pending.push(image);
+		}
+
+		Ok(())
 	}
 
 	pub fn register_build_item<B: BuildItem + 'static>(&mut self, package: &Package, b: B) -> Id {
@@ -32,9 +149,25 @@ impl Context {
 		id
 	}
 
-	pub fn resolve_dependencies(&mut self) -> Result<(), ()> {
+	/// Resolves the packages required by loaded images, registers the build
+	/// items of those images, and links every build item to the items it
+	/// depends on.
+	pub fn resolve_dependencies(&mut self, registry: &Registry) -> Result<()> {
 		use std::cell::RefCell;
 
+		try!(self.resolve_packages(registry));
+
+		for mut image in mem::replace(&mut self.pending, Vec::new()) {
+			try!(registry.configure_image_dependencies(&mut ImageDependencyContext {
+				context: &mut *self,
+				package: &image.package,
+				kind: image.kind,
+				plugin_data: &mut image.plugin_data,
+			}));
+		}
+
+		try!(self.link_phases());
+
 		let deps = RefCell::new(&mut self.dependency_graph);
 		let build_items = &self.build_items;
 		for item in build_items.iter() {
@@ -58,19 +191,286 @@ impl Context {
 				package: package,
 			};
 
-			try!(item.register_dependencies(&mut context));
+			try!(item.register_dependencies(&mut context).map_err(|e| e.context(package, &**item)));
+		}
+
+		Ok(())
+	}
+
+	/// Picks a single version of every required package, preferring the
+	/// versions pinned in the lockfile, and loads the recipes of the chosen
+	/// packages from the index.
+	fn resolve_packages(&mut self, registry: &Registry) -> Result<()> {
+		let mut loaded = BTreeSet::new();
+
+		loop {
+			// Recipes loaded from the index add requirements of their own, so
+			// only the final resolution may change the lockfile.
+			let missing: BTreeSet<PathBuf> = {
+				let mut lockfile = self.lockfile.lock().unwrap().clone();
+				let resolved = try!(self.resolver().resolve(&mut lockfile, LockMode::Update));
+				resolved.iter()
+					.filter(|package| !self.images.contains_key(*package))
+					.filter_map(|package| self.index.recipe_path(package))
+					.map(Path::to_path_buf)
+					.collect()
+			};
+
+			if missing.is_empty() {
+				break
+			}
+
+			for path in missing {
+				if !loaded.insert(path.clone()) {
+					return Err(Error::message(error::Stage::Dependencies, format!("{} no longer declares the images it was indexed with", path.display())))
+				}
+
+				let recipe = try!(File::open(&path).and_then(|mut f| parse::parse(&mut f, registry)).map_err(|e|
+					Error::new(error::Stage::Dependencies, e).with_message(format!("failed to load {}", path.display()))
+				));
+				try!(self.load_recipe(registry, recipe));
+			}
+		}
+
+		let resolved = try!(self.resolver().resolve(&mut self.lockfile.lock().unwrap(), self.lock_mode));
+		self.resolved = resolved.into_iter().map(|package| (package.name.clone(), package)).collect();
+		Ok(())
+	}
+
+	/// A resolver for the requirements of every loaded image.
+	fn resolver(&self) -> Resolver {
+		let mut resolver = Resolver::new(&self.packages);
+		for requirement in &self.requirements {
+			resolver.require(requirement.query.clone(), requirement.required_by.as_ref());
+		}
+		resolver
+	}
+
+	/// Makes the items of each package depend on the items of the nearest
+	/// earlier phase of the same package.
+	fn link_phases(&mut self) -> Result<()> {
+		let order = try!(self.phases.order());
+
+		let mut packages: BTreeMap<&Package, BTreeMap<usize, Vec<Id>>> = BTreeMap::new();
+		for &(id, ref package, ref item) in self.build_items.iter() {
+			if let Some(phase) = item.class() {
+				let rank = try!(order.iter().position(|p| p == &phase).ok_or_else(||
+					Error::message(error::Stage::Dependencies, format!("unknown build phase `{}`", phase)).context(package, &**item)
+				));
+				packages.entry(package).or_insert_with(BTreeMap::new).entry(rank).or_insert_with(Vec::new).push(id);
+			}
+		}
+
+		for phases in packages.values() {
+			let mut previous: Option<&Vec<Id>> = None;
+			for ids in phases.values() {
+				if let Some(previous) = previous {
+					for &id in ids {
+						for &dep in previous {
+							self.dependency_graph.link(id, dep);
+						}
+					}
+				}
+				previous = Some(ids);
+			}
 		}
 
 		Ok(())
 	}
 
 	pub fn image_dependency(&self, package: &Package) -> Option<Id> {
-		self.build_items.dependencies_matching(package, build::BUILD_CLASS_IMAGE.id()).into_iter().next().map(|(id, _)| id)
+		self.build_items.dependencies_matching(package, &phase::PHASE_IMAGE).into_iter().next().map(|(id, _)| id)
 	}
+
+	/// Builds the image of `target` along with everything it depends on.
+	///
+	/// Independent items run concurrently, up to `options.jobs` at a time.
+	/// Items that have already been built by this context are skipped.
+	pub fn build(&mut self, target: &Package, workspace: &Workspace, options: &BuildOptions, source_filesystem: &Filesystem) -> Result<()> {
+		let image = try!(self.image_dependency(target).ok_or_else(||
+			Error::message(error::Stage::Build, "no image build item registered").with_package(target)
+		));
+
+		let order = try!(self.dependency_graph.order(image).map_err(|e|
+			Error::message(error::Stage::Dependencies, format!("failed to order build items: {:?}", e)).with_package(target)
+		));
+
+		for &id in &order {
+			if self.build_items.get(id).is_none() {
+				return Err(Error::message(error::Stage::Build, format!("unknown build item {:?}", id)).with_package(target))
+			}
+		}
+
+		let mut schedule = Schedule::new(&self.dependency_graph, &order, &self.completed);
+		let jobs = cmp::max(options.jobs, 1);
+		let build_items = &self.build_items;
+		let console = &*self.console;
+		let layers = &self.layers;
+		let lockfile = &self.lockfile;
+		let lock_mode = self.lock_mode;
+		let completed = &mut self.completed;
+		let mut errors = Vec::new();
+
+		crossbeam::scope(|scope| {
+			let (tx, rx) = mpsc::channel();
+			let mut running = 0;
+
+			loop {
+				while running < jobs && (errors.is_empty() || options.keep_going) {
+					let id = match schedule.next_ready() {
+						Some(id) => id,
+						None => break,
+					};
+					let (package, item) = build_items.get(id).expect("checked above");
+					let tx = tx.clone();
+
+					running += 1;
+					scope.spawn(move || {
+						let name = item.to_string();
+						let item_console = TaggedConsole::new(console, format!("{} {}", package.name, name));
+						item_console.event(&Event::new(EventKind::ItemStarted));
+
+						// A panicking item must still report back, or the
+						// scheduler would wait on it forever.
+						let result = panic::catch_unwind(AssertUnwindSafe(|| {
+							try!(workspace.prepare(package).map_err(|e| Error::new(error::Stage::Build, e)));
+
+							let layers = layer_chain(layers, package);
+							let mut context = build::BuildContext {
+								package: package,
+								layers: &layers,
+								workspace: workspace,
+								console: &item_console,
+								source_filesystem: source_filesystem,
+								lockfile: lockfile,
+								lock_mode: lock_mode,
+							};
+
+							item.build(&mut context)
+						})).unwrap_or_else(|payload| {
+							let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+								.or_else(|| payload.downcast_ref::<String>().cloned())
+								.unwrap_or_else(|| "unknown panic".into());
+							Err(Error::message(error::Stage::Build, format!("build item panicked: {}", message)))
+						}).map_err(|e| e.context(package, item));
+
+						item_console.event(&Event::new(match result {
+							Ok(()) => EventKind::ItemFinished,
+							Err(ref e) => EventKind::ItemFailed(e),
+						}));
+
+						let log = item_console.into_log();
+						if let Err(e) = workspace.filesystem().write(&workspace.log_path(package, &name), &log) {
+							console.log(LogLevel::Error, format_args!("failed to write log for {} {}: {}", package.name, name, e));
+						}
+
+						let _ = tx.send((id, result));
+					});
+				}
+
+				if running == 0 {
+					break
+				}
+
+				let (id, result) = rx.recv().expect("build thread disconnected");
+				running -= 1;
+				match result {
+					Ok(()) => {
+						completed.insert(id);
+						schedule.complete(id);
+					},
+					Err(e) => errors.push(e),
+				}
+			}
+		});
+
+		if !errors.is_empty() && schedule.blocked() > 0 {
+			console.log(LogLevel::Error, format_args!("{} build items were not run due to earlier failures", schedule.blocked()));
+		}
+
+		match errors.into_iter().next() {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+
+	/// Writes every package, its build items grouped by phase, and what each
+	/// item is ordered after.
+	pub fn write_plan<W: Write>(&self, w: &mut W) -> io::Result<()> {
+		let phases = try!(self.phases.order().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+		let numbers: HashMap<Id, usize> = self.build_items.iter().enumerate().map(|(i, &(id, _, _))| (id, i + 1)).collect();
+
+		for package in &self.packages {
+			let items: Vec<_> = self.build_items.iter().filter(|&&(_, ref p, _)| p == package).collect();
+			if items.is_empty() {
+				continue
+			}
+
+			try!(writeln!(w, "{}-{}{}", package.name, package.version, match self.image_kind(package) {
+				Some(ImageKind::Host) => " [host]",
+				_ => "",
+			}));
+
+			let groups = phases.iter().map(|phase| (phase.name(), items.iter().filter(|&&&(_, _, ref item)| item.class().as_ref() == Some(phase)).collect::<Vec<_>>()))
+				.chain(Some(("(no phase)", items.iter().filter(|&&&(_, _, ref item)| item.class().is_none()).collect())));
+
+			for (phase, items) in groups {
+				if items.is_empty() {
+					continue
+				}
+
+				try!(writeln!(w, "  {}:", phase));
+				for &&&(id, _, ref item) in &items {
+					try!(writeln!(w, "    #{} {}", numbers[&id], item));
+					for dep in self.dependency_graph.dependencies(id) {
+						if let Some((dep_package, dep_item)) = self.build_items.get(*dep) {
+							try!(writeln!(w, "      after #{} {}-{} {}", numbers[dep], dep_package.name, dep_package.version, dep_item));
+						}
+					}
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn is_completed(&self, id: Id) -> bool {
+		self.completed.contains(&id)
+	}
+}
+
+/// Every layer below `package`, each before the layers it is built on.
+///
+/// Layers are visited depth first, and a layer is only placed once all the
+/// layers that depend on it are, so shared layers end up below every image
+/// using them. Otherwise layers keep the order they were declared in.
+fn layer_chain(layers: &BTreeMap<Package, Vec<Package>>, package: &Package) -> Vec<Package> {
+	fn visit<'a>(layers: &'a BTreeMap<Package, Vec<Package>>, package: &Package, visited: &mut BTreeSet<&'a Package>, out: &mut Vec<Package>) {
+		for layer in layers.get(package).map(|l| &l[..]).unwrap_or(&[]).iter().rev() {
+			if visited.insert(layer) {
+				visit(layers, layer, visited, out);
+				out.push(layer.clone());
+			}
+		}
+	}
+
+	let mut out = Vec::new();
+	visit(layers, package, &mut BTreeSet::new(), &mut out);
+	out.reverse();
+	out
 }
 
 impl fmt::Debug for Context {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		unimplemented!()
+		f.debug_struct("Context")
+			.field("packages", &self.packages)
+			.field("images", &self.images)
+			.field("layers", &self.layers)
+			.field("resolved", &self.resolved)
+			.field("phases", &self.phases)
+			.field("build_items", &self.build_items)
+			.field("dependency_graph", &self.dependency_graph)
+			.field("completed", &self.completed)
+			.finish()
 	}
 }
@@ -1,24 +1,53 @@
-use solvent::{DepGraph, DepGraphIterator};
+use std::collections::HashMap;
+use std::fmt;
+use solvent::{DepGraph, DepGraphIterator, SolventError};
 use Id;
 
-pub struct DependencyGraph(DepGraph<Id>);
+pub struct DependencyGraph {
+	graph: DepGraph<Id>,
+	edges: HashMap<Id, Vec<Id>>,
+}
 
 impl DependencyGraph {
 	pub fn new() -> Self {
-		DependencyGraph(DepGraph::new())
+		DependencyGraph {
+			graph: DepGraph::new(),
+			edges: HashMap::new(),
+		}
 	}
 
 	pub fn register(&mut self) -> Id {
 		let id = Id::new();
-		self.0.register_dependencies(id, &[]);
+		self.graph.register_dependencies(id, &[]);
+		self.edges.insert(id, Vec::new());
 		id
 	}
 
 	pub fn link(&mut self, id: Id, dep: Id) {
-		self.0.register_dependency(id, dep)
+		self.graph.register_dependency(id, dep);
+		let deps = self.edges.entry(id).or_insert_with(Vec::new);
+		if !deps.contains(&dep) {
+			deps.push(dep);
+		}
 	}
 
 	pub fn walk(&mut self, id: Id) -> DepGraphIterator<Id> {
-		self.0.dependencies_of(id)
+		self.graph.dependencies_of(id)
+	}
+
+	/// The direct dependencies of `id`.
+	pub fn dependencies(&self, id: Id) -> &[Id] {
+		self.edges.get(&id).map(|deps| &deps[..]).unwrap_or(&[])
+	}
+
+	/// The dependencies of `id` in build order, ending with `id` itself.
+	pub fn order(&mut self, id: Id) -> Result<Vec<Id>, SolventError> {
+		self.walk(id).map(|id| id.map(|id| id.clone())).collect()
+	}
+}
+
+impl fmt::Debug for DependencyGraph {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_map().entries(self.edges.iter()).finish()
 	}
 }
@@ -0,0 +1,159 @@
+use std::error::Error as StdError;
+use std::process::ExitStatus;
+use std::{fmt, result};
+use config::Package;
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// The part of a build an error occurred in, as opposed to the named build
+/// phases items are ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+	Configure,
+	Dependencies,
+	Build,
+}
+
+impl fmt::Display for Stage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			Stage::Configure => "configure",
+			Stage::Dependencies => "dependency resolution",
+			Stage::Build => "build",
+		})
+	}
+}
+
+#[derive(Debug)]
+pub struct Error {
+	stage: Stage,
+	package: Option<Package>,
+	item: Option<String>,
+	message: Option<String>,
+	status: Option<ExitStatus>,
+	cause: Option<Box<StdError + Send + Sync>>,
+}
+
+impl Error {
+	pub fn new<E: Into<Box<StdError + Send + Sync>>>(stage: Stage, cause: E) -> Self {
+		Error {
+			stage: stage,
+			package: None,
+			item: None,
+			message: None,
+			status: None,
+			cause: Some(cause.into()),
+		}
+	}
+
+	pub fn message<S: Into<String>>(stage: Stage, message: S) -> Self {
+		Error {
+			stage: stage,
+			package: None,
+			item: None,
+			message: Some(message.into()),
+			status: None,
+			cause: None,
+		}
+	}
+
+	pub fn status(stage: Stage, status: ExitStatus) -> Self {
+		Error {
+			stage: stage,
+			package: None,
+			item: None,
+			message: None,
+			status: Some(status),
+			cause: None,
+		}
+	}
+
+	/// Attaches the package and build item the error occurred in, unless a
+	/// more specific context has already been recorded.
+	pub fn context<I: fmt::Display + ?Sized>(mut self, package: &Package, item: &I) -> Self {
+		if self.package.is_none() {
+			self.package = Some(package.clone());
+		}
+		if self.item.is_none() {
+			self.item = Some(item.to_string());
+		}
+		self
+	}
+
+	pub fn with_package(mut self, package: &Package) -> Self {
+		if self.package.is_none() {
+			self.package = Some(package.clone());
+		}
+		self
+	}
+
+	pub fn with_message<S: Into<String>>(mut self, message: S) -> Self {
+		self.message = Some(message.into());
+		self
+	}
+
+	pub fn with_status(mut self, status: ExitStatus) -> Self {
+		self.status = Some(status);
+		self
+	}
+
+	pub fn stage(&self) -> Stage {
+		self.stage
+	}
+
+	pub fn package(&self) -> Option<&Package> {
+		self.package.as_ref()
+	}
+
+	pub fn item(&self) -> Option<&str> {
+		self.item.as_ref().map(|s| &s[..])
+	}
+
+	pub fn exit_status(&self) -> Option<ExitStatus> {
+		self.status
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		try!(write!(f, "{} failed", self.stage));
+
+		if let Some(ref package) = self.package {
+			try!(write!(f, " in {}-{}", package.name, package.version));
+		}
+
+		if let Some(ref item) = self.item {
+			try!(write!(f, " ({})", item));
+		}
+
+		if let Some(ref message) = self.message {
+			try!(write!(f, ": {}", message));
+		}
+
+		if let Some(ref status) = self.status {
+			try!(write!(f, ": command exited with {}", status));
+		}
+
+		let mut cause = self.cause.as_ref().map(|e| &**e as &StdError);
+		while let Some(e) = cause {
+			try!(write!(f, ": {}", e));
+			cause = e.cause();
+		}
+
+		Ok(())
+	}
+}
+
+impl StdError for Error {
+	fn description(&self) -> &str {
+		match self.stage {
+			Stage::Configure => "configure failed",
+			Stage::Dependencies => "dependency resolution failed",
+			Stage::Build => "build failed",
+		}
+	}
+
+	fn cause(&self) -> Option<&StdError> {
+		self.cause.as_ref().map(|e| &**e as &StdError)
+	}
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf, Component};
+use std::sync::Mutex;
+use std::io;
+use std::fs;
+
+/// The filesystem operations build items and the workspace rely on.
+pub trait Filesystem: Send + Sync {
+	fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+	fn write(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+	fn remove_file(&self, path: &Path) -> io::Result<()>;
+	fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+	fn exists(&self, path: &Path) -> bool;
+	/// Lists the entries of a directory, sorted by name.
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+}
+
+/// The real, on-disk filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFilesystem;
+
+impl Filesystem for OsFilesystem {
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+		fs::create_dir_all(path)
+	}
+
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		use std::io::Read;
+
+		let mut data = Vec::new();
+		try!(try!(fs::File::open(path)).read_to_end(&mut data));
+		Ok(data)
+	}
+
+	fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+		use std::io::Write;
+
+		try!(fs::File::create(path)).write_all(data)
+	}
+
+	fn remove_file(&self, path: &Path) -> io::Result<()> {
+		fs::remove_file(path)
+	}
+
+	fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+		use std::os::unix::fs::PermissionsExt;
+
+		fs::set_permissions(path, fs::Permissions::from_mode(mode))
+	}
+
+	fn exists(&self, path: &Path) -> bool {
+		fs::metadata(path).is_ok()
+	}
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+		let mut entries = Vec::new();
+		for entry in try!(fs::read_dir(path)) {
+			entries.push(try!(entry).path());
+		}
+		entries.sort();
+		Ok(entries)
+	}
+}
+
+#[derive(Debug, Clone)]
+enum MemoryEntry {
+	Dir,
+	File {
+		data: Vec<u8>,
+		mode: u32,
+	},
+}
+
+/// An in-memory filesystem, so build items can be tested without touching disk.
+#[derive(Debug, Default)]
+pub struct MemoryFilesystem {
+	entries: Mutex<BTreeMap<PathBuf, MemoryEntry>>,
+}
+
+impl MemoryFilesystem {
+	pub fn new() -> Self {
+		MemoryFilesystem::default()
+	}
+
+	pub fn mode(&self, path: &Path) -> Option<u32> {
+		match self.entries.lock().unwrap().get(&normalize(path)) {
+			Some(&MemoryEntry::File { mode, .. }) => Some(mode),
+			_ => None,
+		}
+	}
+}
+
+fn normalize(path: &Path) -> PathBuf {
+	let mut out = PathBuf::from("/");
+	for component in path.components() {
+		match component {
+			Component::Normal(c) => out.push(c),
+			Component::ParentDir => { out.pop(); },
+			_ => (),
+		}
+	}
+	out
+}
+
+fn ancestors(path: &Path) -> Vec<&Path> {
+	let mut out = Vec::new();
+	let mut dir = Some(path);
+	while let Some(d) = dir {
+		out.push(d);
+		dir = d.parent();
+	}
+	out
+}
+
+fn not_found(path: &Path) -> io::Error {
+	io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+impl Filesystem for MemoryFilesystem {
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+		let path = normalize(path);
+		let mut entries = self.entries.lock().unwrap();
+		for dir in &ancestors(&path) {
+			match entries.get(*dir) {
+				Some(&MemoryEntry::File { .. }) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is a file", dir.display()))),
+				_ => (),
+			}
+		}
+		for dir in ancestors(&path) {
+			entries.insert(dir.to_path_buf(), MemoryEntry::Dir);
+		}
+		Ok(())
+	}
+
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		match self.entries.lock().unwrap().get(&normalize(path)) {
+			Some(&MemoryEntry::File { ref data, .. }) => Ok(data.clone()),
+			Some(&MemoryEntry::Dir) => Err(io::Error::new(io::ErrorKind::Other, format!("{} is a directory", path.display()))),
+			None => Err(not_found(path)),
+		}
+	}
+
+	fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+		let path = normalize(path);
+		let mut entries = self.entries.lock().unwrap();
+		match path.parent().map(|p| entries.get(p)) {
+			Some(Some(&MemoryEntry::Dir)) | None => (),
+			_ => return Err(not_found(path.parent().unwrap())),
+		}
+
+		let mode = match entries.get(&path) {
+			Some(&MemoryEntry::File { mode, .. }) => mode,
+			Some(&MemoryEntry::Dir) => return Err(io::Error::new(io::ErrorKind::Other, format!("{} is a directory", path.display()))),
+			None => 0o644,
+		};
+		entries.insert(path, MemoryEntry::File {
+			data: data.to_owned(),
+			mode: mode,
+		});
+		Ok(())
+	}
+
+	fn remove_file(&self, path: &Path) -> io::Result<()> {
+		let mut entries = self.entries.lock().unwrap();
+		match entries.remove(&normalize(path)) {
+			Some(MemoryEntry::File { .. }) => Ok(()),
+			Some(entry) => {
+				entries.insert(normalize(path), entry);
+				Err(io::Error::new(io::ErrorKind::Other, format!("{} is a directory", path.display())))
+			},
+			None => Err(not_found(path)),
+		}
+	}
+
+	fn set_mode(&self, path: &Path, new_mode: u32) -> io::Result<()> {
+		match self.entries.lock().unwrap().get_mut(&normalize(path)) {
+			Some(&mut MemoryEntry::File { ref mut mode, .. }) => {
+				*mode = new_mode;
+				Ok(())
+			},
+			Some(&mut MemoryEntry::Dir) => Ok(()),
+			None => Err(not_found(path)),
+		}
+	}
+
+	fn exists(&self, path: &Path) -> bool {
+		self.entries.lock().unwrap().contains_key(&normalize(path))
+	}
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+		let path = normalize(path);
+		let entries = self.entries.lock().unwrap();
+		match entries.get(&path) {
+			Some(&MemoryEntry::Dir) => Ok(entries.keys().filter(|p| p.parent() == Some(&path)).cloned().collect()),
+			Some(..) => Err(io::Error::new(io::ErrorKind::Other, format!("{} is not a directory", path.display()))),
+			None => Err(not_found(&path)),
+		}
+	}
+}
+
+/// A view of another filesystem rooted at a subdirectory.
+pub struct SubFilesystem<'a> {
+	inner: &'a Filesystem,
+	root: PathBuf,
+}
+
+impl<'a> SubFilesystem<'a> {
+	pub fn new<P: Into<PathBuf>>(inner: &'a Filesystem, root: P) -> Self {
+		SubFilesystem {
+			inner: inner,
+			root: root.into(),
+		}
+	}
+
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+
+	fn path(&self, path: &Path) -> PathBuf {
+		self.root.join(normalize(path).strip_prefix("/").expect("normalized paths are absolute"))
+	}
+}
+
+impl<'a> Filesystem for SubFilesystem<'a> {
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> { self.inner.create_dir_all(&self.path(path)) }
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> { self.inner.read(&self.path(path)) }
+	fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> { self.inner.write(&self.path(path), data) }
+	fn remove_file(&self, path: &Path) -> io::Result<()> { self.inner.remove_file(&self.path(path)) }
+	fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> { self.inner.set_mode(&self.path(path), mode) }
+	fn exists(&self, path: &Path) -> bool { self.inner.exists(&self.path(path)) }
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+		self.inner.read_dir(&self.path(path)).map(|entries| entries.into_iter()
+			.filter_map(|p| p.strip_prefix(&self.root).ok().map(|p| Path::new("/").join(p)))
+			.collect()
+		)
+	}
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::env;
+use serde::Deserialize;
+use toml;
+use config::{Package, PackageQuery};
+use parse;
+
+/// The environment variable holding extra recipe directories, separated like `$PATH`.
+pub const ENCAGE_PATH: &'static str = "ENCAGE_PATH";
+
+/// Recipe files found on the search path, indexed by the package of every
+/// image they declare.
+#[derive(Debug, Clone, Default)]
+pub struct PackageIndex {
+	recipes: BTreeMap<Package, PathBuf>,
+}
+
+impl PackageIndex {
+	pub fn new() -> Self {
+		PackageIndex::default()
+	}
+
+	/// The directories searched for recipes: `$ENCAGE_PATH`, followed by the
+	/// `path` list in the per-user `encage/config.toml`.
+	pub fn search_path() -> io::Result<Vec<PathBuf>> {
+		let mut path: Vec<PathBuf> = env::var_os(ENCAGE_PATH).map(|p| env::split_paths(&p).collect()).unwrap_or_else(Vec::new);
+
+		if let Some(config) = user_config_path() {
+			path.extend(try!(read_user_search_path(&config)));
+		}
+
+		Ok(path)
+	}
+
+	/// Builds an index from every directory on the search path.
+	pub fn from_search_path() -> io::Result<Self> {
+		let mut index = PackageIndex::new();
+		for dir in try!(PackageIndex::search_path()) {
+			try!(index.add_dir(&dir));
+		}
+		Ok(index)
+	}
+
+	/// Indexes every `*.toml` recipe in `dir`. Missing directories are skipped.
+	///
+	/// When several recipes declare the same image package and version, the
+	/// first one found on the search path wins.
+	pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
+		let entries = match fs::read_dir(dir) {
+			Ok(entries) => entries,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+			Err(e) => return Err(e),
+		};
+
+		let mut paths = Vec::new();
+		for entry in entries {
+			let path = try!(entry).path();
+			if path.extension().map(|e| e == "toml").unwrap_or(false) {
+				paths.push(path);
+			}
+		}
+		paths.sort();
+
+		for path in paths {
+			let packages = try!(File::open(&path).and_then(|mut f| parse::parse_image_packages(&mut f)).map_err(|e|
+				io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+			));
+			for package in packages {
+				self.recipes.entry(package).or_insert_with(|| path.clone());
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn packages(&self) -> ::std::collections::btree_map::Keys<Package, PathBuf> {
+		self.recipes.keys()
+	}
+
+	pub fn recipe_path(&self, package: &Package) -> Option<&Path> {
+		self.recipes.get(package).map(|p| p.as_path())
+	}
+
+	/// Finds the highest version of a package matching `query`, along with its recipe.
+	pub fn query(&self, query: &PackageQuery) -> Option<(&Package, &Path)> {
+		self.recipes.iter()
+			.filter(|&(package, _)| package.name == query.name && query.version_req.matches(&package.version))
+			.max_by_key(|&(package, _)| &package.version)
+			.map(|(package, path)| (package, path.as_path()))
+	}
+}
+
+fn user_config_path() -> Option<PathBuf> {
+	env_path("XDG_CONFIG_HOME")
+		.or_else(|| env_path("HOME").map(|home| home.join(".config")))
+		.map(|config| config.join("encage").join("config.toml"))
+}
+
+/// An environment variable holding a path, unless it is unset or empty.
+fn env_path(name: &str) -> Option<PathBuf> {
+	env::var_os(name).and_then(|value| if value.is_empty() { None } else { Some(PathBuf::from(value)) })
+}
+
+fn read_user_search_path(config: &Path) -> io::Result<Vec<PathBuf>> {
+	#[derive(Deserialize)]
+	struct Config {
+		#[serde(default)]
+		path: Vec<String>,
+	}
+
+	let mut str = String::new();
+	match File::open(config) {
+		Ok(mut f) => try!(f.read_to_string(&mut str)),
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+	};
+
+	let mut parser = toml::Parser::new(&str);
+	let table = try!(parser.parse().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", config.display(), parser.errors.remove(0)))));
+	let config_dir = config.parent().unwrap_or(config);
+	Config::deserialize(&mut toml::Decoder::new(toml::Value::Table(table)))
+		.map(|c| c.path.into_iter().map(|p| config_dir.join(p)).collect())
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", config.display(), e)))
+}
@@ -1,7 +1,6 @@
 #![plugin(serde_macros)]
-#![feature(plugin, custom_derive, custom_attribute, associated_consts)]
+#![feature(plugin, custom_derive, custom_attribute)]
 
-extern crate filesystem;
 extern crate toml;
 extern crate semver;
 extern crate typemap;
@@ -12,16 +11,26 @@ extern crate url;
 extern crate solvent;
 extern crate snowflake;
 extern crate hyper;
+extern crate crossbeam;
+extern crate crypto;
 
 pub mod build;
+pub mod command;
 pub mod config;
 pub mod console;
 pub mod context;
 pub mod dependencies;
+pub mod error;
+pub mod fs;
+pub mod index;
+pub mod lock;
+pub mod schedule;
 pub mod work;
 pub mod util;
 pub mod parse;
+pub mod phase;
+pub mod resolve;
 pub mod plugins;
 
 pub use snowflake::ProcessUniqueId as Id;
-pub use self::util::static_id::{StaticId, StaticIdDef};
+pub use self::error::{Error, Result};
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::fs::File;
+use std::path::Path;
+use std::error::Error as StdError;
+use semver::Version;
+use serde::Deserialize;
+use toml;
+use config::Package;
+use error::{Error, Stage, Result};
+
+pub const LOCKFILE_NAME: &'static str = "encage.lock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+	/// The lockfile may be updated to match the resolved packages.
+	Update,
+	/// Resolution fails rather than changing the lockfile.
+	Locked,
+}
+
+/// Exact package versions and fetched file hashes pinned by a previous build.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lockfile {
+	packages: BTreeMap<String, Version>,
+	files: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct SerializeLockfile {
+	#[serde(default)]
+	package: Vec<SerializeLockedPackage>,
+	#[serde(default)]
+	file: Vec<SerializeLockedFile>,
+}
+
+#[derive(Deserialize)]
+struct SerializeLockedPackage {
+	name: String,
+	version: String,
+}
+
+#[derive(Deserialize)]
+struct SerializeLockedFile {
+	url: String,
+	hash: String,
+}
+
+impl Lockfile {
+	pub fn new() -> Self {
+		Lockfile::default()
+	}
+
+	/// Loads the lockfile at `path`, or an empty one if it doesn't exist yet.
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		match File::open(path) {
+			Ok(mut f) => Lockfile::read(&mut f),
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Lockfile::new()),
+			Err(e) => Err(e),
+		}
+	}
+
+	pub fn read<R: Read>(r: &mut R) -> io::Result<Self> {
+		fn err<E: Into<Box<StdError + Send + Sync>>>(e: E) -> io::Error {
+			io::Error::new(io::ErrorKind::InvalidData, e)
+		}
+
+		let mut str = String::new();
+		try!(r.read_to_string(&mut str));
+
+		let mut parser = toml::Parser::new(&str);
+		let table = try!(parser.parse().ok_or_else(|| err(parser.errors.remove(0))));
+		let lock = try!(SerializeLockfile::deserialize(&mut toml::Decoder::new(toml::Value::Table(table))).map_err(err));
+
+		Ok(Lockfile {
+			packages: try!(lock.package.into_iter().map(|p| Version::parse(&p.version).map(|v| (p.name, v)).map_err(|e| err(e.description()))).collect()),
+			files: lock.file.into_iter().map(|f| (f.url, f.hash)).collect(),
+		})
+	}
+
+	pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+		try!(writeln!(w, "# This file is generated by encage-build. Do not edit it by hand."));
+
+		for (name, version) in &self.packages {
+			try!(writeln!(w, "\n[[package]]"));
+			try!(writeln!(w, "name = {}", toml::Value::String(name.clone())));
+			try!(writeln!(w, "version = {}", toml::Value::String(version.to_string())));
+		}
+
+		for (url, hash) in &self.files {
+			try!(writeln!(w, "\n[[file]]"));
+			try!(writeln!(w, "url = {}", toml::Value::String(url.clone())));
+			try!(writeln!(w, "hash = {}", toml::Value::String(hash.clone())));
+		}
+
+		Ok(())
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		let mut f = try!(File::create(path));
+		self.write(&mut f)
+	}
+
+	pub fn package(&self, name: &str) -> Option<&Version> {
+		self.packages.get(name)
+	}
+
+	pub fn file(&self, url: &str) -> Option<&str> {
+		self.files.get(url).map(|s| &s[..])
+	}
+
+	/// Pins `package` to its exact version.
+	pub fn lock_package(&mut self, package: &Package, mode: LockMode) -> Result<()> {
+		if self.packages.get(&package.name) == Some(&package.version) {
+			return Ok(())
+		}
+
+		if mode == LockMode::Locked {
+			return Err(Error::message(Stage::Dependencies, format!("{} would change {} to version {}, but the lockfile is locked",
+				LOCKFILE_NAME, package.name, package.version)))
+		}
+
+		self.packages.insert(package.name.clone(), package.version.clone());
+		Ok(())
+	}
+
+	/// Pins the hash of a fetched file, failing if it differs from the pinned one.
+	pub fn lock_file(&mut self, url: &str, hash: &str, mode: LockMode) -> Result<()> {
+		match self.files.get(url) {
+			Some(locked) if locked == hash => return Ok(()),
+			Some(locked) => return Err(Error::message(Stage::Build, format!("{} changed: expected hash {}, fetched {}", url, locked, hash))),
+			None if mode == LockMode::Locked => return Err(Error::message(Stage::Build, format!("{} is not pinned in {}, but the lockfile is locked", url, LOCKFILE_NAME))),
+			None => (),
+		}
+
+		self.files.insert(url.to_owned(), hash.to_owned());
+		Ok(())
+	}
+
+	/// Drops pinned packages that are no longer part of the resolution.
+	pub fn retain_packages<'a, I: IntoIterator<Item=&'a Package>>(&mut self, packages: I, mode: LockMode) -> Result<()> {
+		let names: Vec<&str> = packages.into_iter().map(|p| &p.name[..]).collect();
+		let stale: Vec<String> = self.packages.keys().filter(|name| !names.contains(&&name[..])).cloned().collect();
+
+		if let Some(name) = stale.first() {
+			if mode == LockMode::Locked {
+				return Err(Error::message(Stage::Dependencies, format!("{} would drop {}, but the lockfile is locked", LOCKFILE_NAME, name)))
+			}
+		}
+
+		for name in stale {
+			self.packages.remove(&name);
+		}
+
+		Ok(())
+	}
+}
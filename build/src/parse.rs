@@ -5,8 +5,10 @@ use serde::{de, Serialize, Serializer, Deserialize, Deserializer};
 use serde_value::{self, Value, DeserializerError};
 use std::error::Error;
 use std::{io, fmt};
+use toml;
 use plugins::{Registry, ImageConfigurationContext};
-use config::Package;
+use config::{Package, Namespace};
+use phase::{Phase, PhaseDecl};
 
 #[derive(Debug)]
 pub struct PluginData(DebugMap);
@@ -31,6 +33,13 @@ impl PluginData {
 
 pub type UserData = BTreeMap<String, Value>;
 
+#[derive(Debug)]
+pub struct Recipe {
+	pub package: Package,
+	pub phases: Vec<PhaseDecl>,
+	pub images: Vec<ImageDesc>,
+}
+
 #[derive(Debug)]
 pub struct ImageDesc {
 	pub package: Package,
@@ -38,7 +47,7 @@ pub struct ImageDesc {
 	pub plugin_data: PluginData,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImageKind {
 	Host,
 	Container,
@@ -49,14 +58,37 @@ struct SerializePackage {
 	name: String,
 	version: SerializeVersion,
 	plugins: Vec<String>,
+	#[serde(default)]
+	aliases: BTreeMap<String, String>,
 }
 
 #[derive(Deserialize)]
 struct SerializeRoot {
 	package: SerializePackage,
+	#[serde(default)]
+	phase: Vec<SerializePhase>,
 	image: Vec<SerializeImageDesc>,
 }
 
+#[derive(Deserialize)]
+struct SerializePhase {
+	name: String,
+	#[serde(default)]
+	after: Vec<String>,
+	#[serde(default)]
+	before: Vec<String>,
+}
+
+impl SerializePhase {
+	fn into_decl(self) -> PhaseDecl {
+		PhaseDecl {
+			phase: Phase::new(self.name),
+			after: self.after.into_iter().map(Phase::new).collect(),
+			before: self.before.into_iter().map(Phase::new).collect(),
+		}
+	}
+}
+
 struct SerializeImageDesc {
 	name: String,
 	kind: ImageKind,
@@ -107,6 +139,14 @@ impl Deserialize for ImageKind {
 }
 
 impl ImageKind {
+	/// Marks steps that run directly on the host in plans and logs.
+	pub fn log_prefix(&self) -> &'static str {
+		match *self {
+			ImageKind::Host => "[host] ",
+			ImageKind::Container => "",
+		}
+	}
+
 	pub fn from_str<S: AsRef<str>>(str: S) -> Option<Self> {
 		Some(match str.as_ref() {
 			"host" => ImageKind::Host,
@@ -140,56 +180,170 @@ impl Serialize for SerializeVersion {
 	}
 }
 
-pub fn parse<R: io::Read>(r: &mut R, plugin_registry: &Registry) -> io::Result<Vec<ImageDesc>> {
-	use toml::{self, Decoder, Parser};
-	fn err<E: Into<Box<Error + Send + Sync>>>(e: E) -> io::Error {
-		io::Error::new(io::ErrorKind::InvalidData, e)
-	}
+fn err<E: Into<Box<Error + Send + Sync>>>(e: E) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, e)
+}
 
-	let mut str = String::new();
-	try!(r.read_to_string(&mut str));
-	str = str.replace("\t", "    ");
+fn read_table<R: io::Read>(r: &mut R) -> io::Result<toml::Table> {
+	let mut input = String::new();
+	try!(r.read_to_string(&mut input));
 
-	let mut parser = Parser::new(&str);
+	let (escaped, inserted) = escape_tabs(&input);
+	let mut parser = toml::Parser::new(&escaped);
 	let table = parser.parse();
-	if !parser.errors.is_empty() {
-		return Err(err(parser.errors.into_iter().next().expect("unreachable")))
-	}
-
-	if let Some(table) = table {
-		let root = try!(SerializeRoot::deserialize(&mut Decoder::new(toml::Value::Table(table))).map_err(err));
-
-		let root_package = Package {
-			name: root.package.name,
-			version: root.package.version.0,
-		};
-
-		let plugins = root.package.plugins;
-		let plugins = plugins.iter().collect::<Vec<_>>();
-
-		let images = try!(root.image.into_iter().map(|i| {
-			let package = root_package.clone().into_absolute(i.name);
-			let kind = i.kind;
-			let mut user_data = i.user_data;
-			let mut plugin_data = PluginData::new();
-			plugin_registry.configure_image(&plugins, &mut ImageConfigurationContext {
-				user_data: &mut user_data,
-				plugin_data: &mut plugin_data,
-				package: &package,
-				root_package: &root_package,
-			}).and_then(|_| if let Some((key, _)) = user_data.iter().next() {
-				Err(de::Error::unknown_field(key))
-			} else {
-				Ok(())
-			}).map(move |_| ImageDesc {
-				package: package,
-				kind: kind,
-				plugin_data: plugin_data,
-			})
-		}).collect::<Result<_, _>>().map_err(err));
+	if let Some(e) = parser.errors.into_iter().next() {
+		let offset = e.lo - inserted.iter().take_while(|&&i| i <= e.lo).count();
+		let (line, col) = line_col(&input, offset);
+		return Err(err(format!("{}:{}: {}", line, col, e.desc)))
+	}
+
+	table.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty input"))
+}
 
-		Ok(images)
-	} else {
-		Err(io::Error::new(io::ErrorKind::InvalidData, "empty input"))
+/// Escapes raw tabs inside basic strings as `\t`.
+///
+/// The toml parser rejects them as control characters even though they're
+/// valid, and they're common in multi-line shell commands. The decoded values
+/// are unchanged. Also returns the offset of every inserted byte, so parser
+/// errors can be mapped back onto the original input.
+fn escape_tabs(input: &str) -> (String, Vec<usize>) {
+	#[derive(Clone, Copy, PartialEq)]
+	enum State {
+		Normal,
+		Comment,
+		Basic,
+		MultiBasic,
+		Literal,
+		MultiLiteral,
 	}
+
+	let mut out = String::with_capacity(input.len());
+	let mut inserted = Vec::new();
+	let mut state = State::Normal;
+	let mut i = 0;
+
+	while let Some(c) = input[i..].chars().next() {
+		let rest = &input[i..];
+		let mut len = c.len_utf8();
+		match state {
+			State::Normal => match c {
+				'#' => state = State::Comment,
+				'"' if rest.starts_with("\"\"\"") => { state = State::MultiBasic; len = 3 },
+				'"' => state = State::Basic,
+				'\'' if rest.starts_with("'''") => { state = State::MultiLiteral; len = 3 },
+				'\'' => state = State::Literal,
+				_ => (),
+			},
+			State::Comment => if c == '\n' { state = State::Normal },
+			State::Basic | State::MultiBasic => match c {
+				'\\' => len += rest[1..].chars().next().map(|c| c.len_utf8()).unwrap_or(0),
+				'\t' => {
+					out.push('\\');
+					inserted.push(out.len());
+					out.push('t');
+					i += 1;
+					continue
+				},
+				'"' if state == State::Basic => state = State::Normal,
+				'"' if rest.starts_with("\"\"\"") => { state = State::Normal; len = 3 },
+				_ => (),
+			},
+			State::Literal => if c == '\'' { state = State::Normal },
+			State::MultiLiteral => if rest.starts_with("'''") { state = State::Normal; len = 3 },
+		}
+
+		out.push_str(&rest[..len]);
+		i += len;
+	}
+
+	(out, inserted)
+}
+
+/// The 1-based line and column of a byte offset into `input`.
+fn line_col(input: &str, offset: usize) -> (usize, usize) {
+	let offset = ::std::cmp::min(offset, input.len());
+	let before = &input[..offset];
+	let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+	(before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Reads the package of every image a recipe declares, without configuring
+/// any of them.
+pub fn parse_image_packages<R: io::Read>(r: &mut R) -> io::Result<Vec<Package>> {
+	#[derive(Deserialize)]
+	struct Header {
+		package: HeaderPackage,
+		#[serde(default)]
+		image: Vec<HeaderImage>,
+	}
+
+	#[derive(Deserialize)]
+	struct HeaderPackage {
+		name: String,
+		version: SerializeVersion,
+		#[serde(default)]
+		aliases: BTreeMap<String, String>,
+	}
+
+	#[derive(Deserialize)]
+	struct HeaderImage {
+		name: String,
+	}
+
+	let table = try!(read_table(r));
+	let header = try!(Header::deserialize(&mut toml::Decoder::new(toml::Value::Table(table))).map_err(err));
+
+	let namespace = Namespace {
+		root: Package {
+			name: header.package.name,
+			version: header.package.version.0,
+		},
+		aliases: header.package.aliases,
+	};
+
+	header.image.into_iter().map(|image| namespace.resolve_package(&image.name).map_err(err)).collect()
+}
+
+pub fn parse<R: io::Read>(r: &mut R, plugin_registry: &Registry) -> io::Result<Recipe> {
+	let table = try!(read_table(r));
+
+	let root = try!(SerializeRoot::deserialize(&mut toml::Decoder::new(toml::Value::Table(table))).map_err(err));
+
+	let root_package = Package {
+		name: root.package.name,
+		version: root.package.version.0,
+	};
+
+	let namespace = Namespace {
+		root: root_package.clone(),
+		aliases: root.package.aliases,
+	};
+
+	let plugins = root.package.plugins;
+	let plugins = plugins.iter().collect::<Vec<_>>();
+
+	let images = try!(root.image.into_iter().map(|i| {
+		let package = try!(namespace.resolve_package(&i.name).map_err(|e| DeserializerError::Syntax(e.to_string())));
+		let kind = i.kind;
+		let mut user_data = i.user_data;
+		let mut plugin_data = PluginData::new();
+		plugin_registry.configure_image(&plugins, &mut ImageConfigurationContext {
+			user_data: &mut user_data,
+			plugin_data: &mut plugin_data,
+			package: &package,
+			kind: kind,
+			root_package: &root_package,
+			namespace: &namespace,
+		}).map(move |_| ImageDesc {
+			package: package,
+			kind: kind,
+			plugin_data: plugin_data,
+		})
+	}).collect::<Result<_, _>>().map_err(err));
+
+	Ok(Recipe {
+		package: root_package,
+		phases: root.phase.into_iter().map(SerializePhase::into_decl).collect(),
+		images: images,
+	})
 }
@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::fmt;
+use error::{self, Error};
+
+/// A named build phase that build items attach to.
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Phase(Cow<'static, str>);
+
+impl Phase {
+	pub fn new<S: Into<Cow<'static, str>>>(name: S) -> Self {
+		Phase(name.into())
+	}
+
+	pub fn name(&self) -> &str {
+		&self.0
+	}
+}
+
+impl fmt::Display for Phase {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl fmt::Debug for Phase {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Phase({})", self.0)
+	}
+}
+
+pub const PHASE_FETCH: Phase = Phase(Cow::Borrowed("fetch"));
+pub const PHASE_STAGE: Phase = Phase(Cow::Borrowed("stage"));
+pub const PHASE_BUILD: Phase = Phase(Cow::Borrowed("build"));
+pub const PHASE_POST: Phase = Phase(Cow::Borrowed("post"));
+pub const PHASE_IMAGE: Phase = Phase(Cow::Borrowed("image"));
+pub const PHASE_EXPORT: Phase = Phase(Cow::Borrowed("export"));
+
+/// A phase declaration along with its ordering constraints.
+#[derive(Debug, Clone)]
+pub struct PhaseDecl {
+	pub phase: Phase,
+	pub after: Vec<Phase>,
+	pub before: Vec<Phase>,
+}
+
+impl PhaseDecl {
+	pub fn new(phase: Phase) -> Self {
+		PhaseDecl {
+			phase: phase,
+			after: Vec::new(),
+			before: Vec::new(),
+		}
+	}
+
+	pub fn after(mut self, phase: Phase) -> Self {
+		self.after.push(phase);
+		self
+	}
+
+	pub fn before(mut self, phase: Phase) -> Self {
+		self.before.push(phase);
+		self
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseRegistry {
+	phases: Vec<PhaseDecl>,
+}
+
+impl PhaseRegistry {
+	pub fn new() -> Self {
+		PhaseRegistry {
+			phases: Vec::new(),
+		}
+	}
+
+	pub fn register_builtins(&mut self) {
+		self.declare(PhaseDecl::new(PHASE_FETCH));
+		self.declare(PhaseDecl::new(PHASE_STAGE).after(PHASE_FETCH));
+		self.declare(PhaseDecl::new(PHASE_BUILD).after(PHASE_STAGE));
+		self.declare(PhaseDecl::new(PHASE_POST).after(PHASE_BUILD));
+		self.declare(PhaseDecl::new(PHASE_IMAGE).after(PHASE_POST));
+		self.declare(PhaseDecl::new(PHASE_EXPORT).after(PHASE_IMAGE));
+	}
+
+	/// Declares a phase, or adds constraints to an existing one.
+	pub fn declare(&mut self, decl: PhaseDecl) {
+		if let Some(existing) = self.phases.iter_mut().find(|p| p.phase == decl.phase) {
+			existing.after.extend(decl.after);
+			existing.before.extend(decl.before);
+			return
+		}
+
+		self.phases.push(decl);
+	}
+
+	pub fn get<S: AsRef<str>>(&self, name: S) -> Option<&Phase> {
+		let name = name.as_ref();
+		self.phases.iter().map(|p| &p.phase).find(|p| p.name() == name)
+	}
+
+	pub fn iter(&self) -> ::std::slice::Iter<PhaseDecl> {
+		self.phases.iter()
+	}
+
+	/// Orders all declared phases so that every constraint is satisfied.
+	///
+	/// Unconstrained phases keep their declaration order.
+	pub fn order(&self) -> error::Result<Vec<Phase>> {
+		let index = |phase: &Phase| self.phases.iter().position(|p| &p.phase == phase);
+
+		let mut edges = vec![Vec::new(); self.phases.len()];
+		for (i, decl) in self.phases.iter().enumerate() {
+			for after in &decl.after {
+				edges[i].push(try!(index(after).ok_or_else(|| unknown(&decl.phase, after))));
+			}
+			for before in &decl.before {
+				edges[try!(index(before).ok_or_else(|| unknown(&decl.phase, before)))].push(i);
+			}
+		}
+
+		let mut order = Vec::with_capacity(self.phases.len());
+		let mut done = vec![false; self.phases.len()];
+		while order.len() < self.phases.len() {
+			let next = try!((0..self.phases.len()).find(|&i| !done[i] && edges[i].iter().all(|&dep| done[dep])).ok_or_else(|| {
+				let cycle: Vec<_> = (0..self.phases.len()).filter(|&i| !done[i]).map(|i| self.phases[i].phase.name()).collect();
+				Error::message(error::Stage::Configure, format!("build phases have cyclic ordering constraints: {}", cycle.join(", ")))
+			}));
+			done[next] = true;
+			order.push(self.phases[next].phase.clone());
+		}
+
+		Ok(order)
+	}
+}
+
+fn unknown(phase: &Phase, constraint: &Phase) -> Error {
+	Error::message(error::Stage::Configure, format!("build phase `{}` is ordered relative to unknown phase `{}`", phase, constraint))
+}
@@ -14,6 +14,10 @@ impl Plugin for BuildPlugin {
 		Some("base")
 	}
 
+	fn config_keys(&self) -> &[&str] {
+		&["build", "build-commands"]
+	}
+
 	fn configure_image(&self, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> {
 		if let Some(build) = context.user_data.remove("build") {
 			// TODO
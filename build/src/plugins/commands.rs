@@ -1,5 +1,14 @@
-use serde_value::{Value, DeserializerError};
-use plugins::{Plugin, ImageConfigurationContext};
+use std::fmt;
+use serde_value::DeserializerError;
+use typemap::Key;
+use plugins::{Plugin, ImageConfigurationContext, ImageDependencyContext};
+use plugins::handlebars::HandlebarsPlugin;
+use build::{BuildItem, BuildClass, BuildContext, BuildDependencyContext};
+use command::{Command, Limits, Network, Root};
+use util::text;
+use parse::ImageKind;
+use phase;
+use error::{self, Error};
 
 pub struct CommandsPlugin(());
 
@@ -14,11 +23,161 @@ impl Plugin for CommandsPlugin {
 		Some("base")
 	}
 
+	fn config_keys(&self) -> &[&str] {
+		&["capabilities", "commands", "dedent", "devices", "hostname", "limits", "network", "privileged"]
+	}
+
 	fn configure_image(&self, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> {
+		let dedent = match context.user_data.remove("dedent") {
+			Some(dedent) => try!(dedent.deserialize_into()),
+			None => false,
+		};
+
+		let network = match context.user_data.remove("network") {
+			Some(network) => try!(Network::parse(network)),
+			None => Network::default(),
+		};
+
+		let hostname = match context.user_data.remove("hostname") {
+			Some(hostname) => Some(try!(hostname.deserialize_into())),
+			None => None,
+		};
+
+		let limits = match context.user_data.remove("limits") {
+			Some(limits) => try!(Limits::parse(limits)),
+			None => Limits::default(),
+		};
+
+		let capabilities = match context.user_data.remove("capabilities") {
+			Some(capabilities) => try!(capabilities.deserialize_into()),
+			None => Vec::new(),
+		};
+
+		let privileged = match context.user_data.remove("privileged") {
+			Some(privileged) => try!(privileged.deserialize_into()),
+			None => false,
+		};
+
+		let devices = match context.user_data.remove("devices") {
+			Some(devices) => try!(devices.deserialize_into()),
+			None => Vec::new(),
+		};
+
 		if let Some(commands) = context.user_data.remove("commands") {
-			// TODO
+			let mut commands = try!(Command::parse_list(commands));
+			if dedent {
+				commands = try!(commands.into_iter()
+					.map(|(c, l, n)| c.map_strings(|s| Ok::<_, DeserializerError>(text::dedent(&s))).map(|c| (c, l, n)))
+					.collect()
+				);
+			}
+			if context.plugin_data.get::<HandlebarsPlugin>().is_some() {
+				commands = try!(commands.into_iter()
+					.map(|(c, l, n)| c.map_strings(|s| HandlebarsPlugin::transform_string(s, context)).map(|c| (c, l, n)))
+					.collect()
+				);
+			}
+			context.plugin_data.set::<Self>(Commands {
+				commands: commands,
+				network: network,
+				hostname: hostname,
+				limits: limits,
+				capabilities: capabilities,
+				privileged: privileged,
+				devices: devices,
+			});
 		}
 
 		Ok(())
 	}
+
+	fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> error::Result<()> {
+		if let Some(commands) = context.plugin_data.get::<Self>() {
+			let mut networks = Some(commands.network).into_iter().chain(commands.commands.iter().filter_map(|&(_, _, network)| network));
+			if context.kind == ImageKind::Host && networks.any(|network| network != Network::default()) {
+				return Err(Error::message(error::Stage::Configure, "host images always use the host network").with_package(context.package))
+			}
+			if context.kind == ImageKind::Host && (!commands.limits.is_empty() || commands.commands.iter().any(|&(_, ref l, _)| !l.is_empty())) {
+				return Err(Error::message(error::Stage::Configure, "resource limits are only enforced in containers").with_package(context.package))
+			}
+			if context.kind == ImageKind::Host && commands.hostname.is_some() {
+				return Err(Error::message(error::Stage::Configure, "host commands always see the host's hostname").with_package(context.package))
+			}
+			if context.kind == ImageKind::Host && (!commands.capabilities.is_empty() || commands.privileged) {
+				return Err(Error::message(error::Stage::Configure, "host commands always run with the caller's privileges").with_package(context.package))
+			}
+			if context.kind == ImageKind::Host && !commands.devices.is_empty() {
+				return Err(Error::message(error::Stage::Configure, "host commands always see every host device").with_package(context.package))
+			}
+
+			context.context.register_build_item(context.package, CommandsBuildItem {
+				kind: context.kind,
+				commands: commands.clone(),
+			});
+		}
+
+		Ok(())
+	}
+}
+
+impl Key for CommandsPlugin {
+	type Value = Commands;
+}
+
+#[derive(Debug, Clone)]
+pub struct Commands {
+	/// Each command along with the limits and network it overrides.
+	pub commands: Vec<(Command, Limits, Option<Network>)>,
+	/// The network of every command that doesn't set its own.
+	pub network: Network,
+	/// The hostname commands see.
+	pub hostname: Option<String>,
+	/// Limits of every command that doesn't set its own.
+	pub limits: Limits,
+	/// Capabilities every command keeps beyond the runtime's default set.
+	pub capabilities: Vec<String>,
+	/// Whether commands run without dropping capabilities or filtering syscalls.
+	pub privileged: bool,
+	/// Host devices exposed to commands beyond the runtime's default set, such as `/dev/fuse`.
+	pub devices: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CommandsBuildItem {
+	kind: ImageKind,
+	commands: Commands,
+}
+
+impl BuildItem for CommandsBuildItem {
+	fn class(&self) -> Option<BuildClass> { Some(phase::PHASE_POST) }
+
+	fn register_dependencies(&self, _context: &mut BuildDependencyContext) -> error::Result<()> { Ok(()) }
+
+	fn build(&self, context: &mut BuildContext) -> error::Result<()> {
+		let stage = context.workspace.staging_dir(context.package);
+		let mut root = Root {
+			stage: &stage,
+			resources: context.workspace.resource_dir(context.package),
+			layers: context.layers.iter().map(|p| context.workspace.staging_dir(p)).collect(),
+			overlay_work: context.workspace.work_dir(context.package, "overlay"),
+			network: self.commands.network,
+			hostname: self.commands.hostname.clone(),
+			capabilities: self.commands.capabilities.clone(),
+			privileged: self.commands.privileged,
+			devices: self.commands.devices.clone(),
+		};
+
+		for &(ref command, ref limits, network) in &self.commands.commands {
+			root.network = network.unwrap_or(self.commands.network);
+			try!(command.run(self.kind, &root, &limits.or(&self.commands.limits), context.console));
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Display for CommandsBuildItem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}commands", self.kind.log_prefix())
+	}
 }
@@ -1,5 +1,13 @@
-use serde_value::{Value, DeserializerError};
-use plugins::{Plugin, ImageConfigurationContext};
+use std::fmt;
+use serde_value::DeserializerError;
+use typemap::Key;
+use semver::VersionReq;
+use plugins::{Plugin, ImageConfigurationContext, ImageRequirementContext, ImageDependencyContext};
+use build::{BuildItem, BuildClass, BuildContext, BuildDependencyContext};
+use config::{Package, PackageQuery};
+use parse::ImageKind;
+use phase;
+use error::{self, Error};
 
 pub struct DependsPlugin(());
 
@@ -14,11 +22,108 @@ impl Plugin for DependsPlugin {
 		Some("base")
 	}
 
+	fn config_keys(&self) -> &[&str] {
+		&["depends"]
+	}
+
 	fn configure_image(&self, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> {
 		if let Some(depends) = context.user_data.remove("depends") {
-			// TODO
+			let depends: Vec<String> = match depends.clone().deserialize_into::<String>() {
+				Ok(depends) => vec![depends],
+				Err(..) => try!(depends.deserialize_into()),
+			};
+
+			let namespace = context.namespace;
+			let depends = try!(depends.into_iter().map(|name| {
+				let query = try!(PackageQuery::parse(&name).map_err(|e| DeserializerError::Syntax(format!("invalid dependency {}: {:?}", name, e))));
+
+				// Images of the same recipe share its version unless asked otherwise.
+				let version_req = if name.starts_with('.') && !name.contains('@') {
+					VersionReq::exact(&namespace.root.version)
+				} else {
+					query.version_req
+				};
+
+				namespace.resolve(&query.name).map(|resolved| (name, PackageQuery {
+					name: resolved,
+					version_req: version_req,
+				})).map_err(|e| DeserializerError::Syntax(e.to_string()))
+			}).collect());
+			context.plugin_data.set::<Self>(depends);
+		}
+
+		Ok(())
+	}
+
+	fn image_requirements(&self, context: &mut ImageRequirementContext) {
+		if let Some(queries) = context.plugin_data.get::<Self>() {
+			context.requirements.extend(queries.iter().map(|&(_, ref query)| query.clone()));
+		}
+	}
+
+	fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> error::Result<()> {
+		if let Some(queries) = context.plugin_data.get::<Self>() {
+			let depends: Vec<Package> = try!(queries.iter().map(|&(ref name, ref query)| context.context.resolved_package(&query.name).cloned().ok_or_else(||
+				Error::message(error::Stage::Dependencies, if &query.name != name {
+					format!("dependency `{}` (resolved to {} {}) does not match any package", name, query.name, query.version_req)
+				} else {
+					format!("dependency `{}` does not match any package", name)
+				}).with_package(context.package)
+			)).collect());
+
+			for package in &depends {
+				match context.context.image_kind(package) {
+					Some(ImageKind::Host) if context.kind == ImageKind::Container => return Err(Error::message(error::Stage::Dependencies,
+						format!("container image cannot use host image {} as a runtime layer", package.name)
+					).with_package(context.package)),
+					Some(..) => (),
+					None => return Err(Error::message(error::Stage::Dependencies,
+						format!("dependency {}-{} has no loaded recipe, so its image type is unknown", package.name, package.version)
+					).with_package(context.package)),
+				}
+			}
+
+			context.context.register_layers(context.package, depends.clone());
+			context.context.register_build_item(context.package, DependsBuildItem {
+				depends: depends,
+			});
+		}
+
+		Ok(())
+	}
+}
+
+impl Key for DependsPlugin {
+	/// Each dependency as written in the recipe, and the query it resolved to.
+	type Value = Vec<(String, PackageQuery)>;
+}
+
+/// Orders an image after the images it is layered on top of.
+#[derive(Debug)]
+pub struct DependsBuildItem {
+	depends: Vec<Package>,
+}
+
+impl BuildItem for DependsBuildItem {
+	fn class(&self) -> Option<BuildClass> { Some(phase::PHASE_FETCH) }
+
+	fn register_dependencies(&self, context: &mut BuildDependencyContext) -> error::Result<()> {
+		for package in &self.depends {
+			context.depends_on(package, &phase::PHASE_IMAGE);
 		}
 
 		Ok(())
 	}
+
+	fn build(&self, _context: &mut BuildContext) -> error::Result<()> { Ok(()) }
+}
+
+impl fmt::Display for DependsBuildItem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		try!(f.write_str("depends"));
+		for package in &self.depends {
+			try!(write!(f, " {}", package.name));
+		}
+		Ok(())
+	}
 }
@@ -1,5 +1,17 @@
-use serde_value::{Value, DeserializerError};
-use plugins::{Plugin, ImageConfigurationContext};
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use hyper;
+use serde_value::DeserializerError;
+use typemap::Key;
+use plugins::{Plugin, ImageConfigurationContext, ImageDependencyContext};
+use plugins::handlebars::HandlebarsPlugin;
+use build::{BuildItem, BuildClass, BuildContext, BuildDependencyContext};
+use console::{Event, EventKind};
+use error::{self, Error};
+use fs::Filesystem;
+use phase;
+use util::digest;
 
 pub struct FilesPlugin(());
 
@@ -14,11 +26,209 @@ impl Plugin for FilesPlugin {
 		Some("base")
 	}
 
+	fn config_keys(&self) -> &[&str] {
+		&["files"]
+	}
+
 	fn configure_image(&self, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> {
 		if let Some(files) = context.user_data.remove("files") {
-			// TODO
+			let files: Vec<SerializeFile> = try!(files.deserialize_into());
+			let mut out = Files::default();
+			for file in files {
+				let src = if context.plugin_data.get::<HandlebarsPlugin>().is_some() {
+					try!(HandlebarsPlugin::transform_string(file.src, context))
+				} else {
+					file.src
+				};
+
+				let sha256 = match file.sha256 {
+					Some(ref sha256) if !digest::is_sha256(sha256) => return Err(DeserializerError::Syntax(format!("file {}: invalid sha256 `{}`", src, sha256))),
+					sha256 => sha256.map(|s| s.to_lowercase()),
+				};
+
+				out.inner.push(File {
+					name: file_name(&src).to_owned(),
+					src: src,
+					dest: file.dest,
+					perms: file.perms,
+					sha256: sha256,
+				});
+			}
+			context.plugin_data.set::<Self>(out);
 		}
 
 		Ok(())
 	}
+
+	fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> error::Result<()> {
+		if let Some(files) = context.plugin_data.get::<Self>() {
+			context.context.register_build_item(context.package, FetchBuildItem {
+				files: files.clone(),
+			});
+		}
+
+		Ok(())
+	}
+}
+
+impl Key for FilesPlugin {
+	type Value = Files;
+}
+
+#[derive(Deserialize)]
+struct SerializeFile {
+	src: String,
+	#[serde(default)]
+	dest: Option<String>,
+	#[serde(default)]
+	perms: Option<String>,
+	#[serde(default)]
+	sha256: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Files {
+	inner: Vec<File>,
+}
+
+impl Files {
+	pub fn iter(&self) -> ::std::slice::Iter<File> {
+		self.inner.iter()
+	}
+
+	pub fn find<S: AsRef<str>>(&self, name: S) -> Option<&File> {
+		let name = name.as_ref();
+		self.inner.iter().find(|f| f.name == name || f.dest.as_ref().map(|d| &d[..] == name).unwrap_or(false))
+	}
+}
+
+#[derive(Clone, Debug, Hash)]
+pub struct File {
+	pub name: String,
+	pub src: String,
+	pub dest: Option<String>,
+	pub perms: Option<String>,
+	/// The expected digest of the file's contents, as lowercase hex.
+	pub sha256: Option<String>,
+}
+
+impl File {
+	/// A stable key derived from the source, naming the directory the file
+	/// is fetched into.
+	pub fn key(&self) -> String {
+		digest::sha256(self.src.as_bytes())[..16].to_owned()
+	}
+
+	/// The path of the fetched file below `resources`, the image's
+	/// resource directory as its commands see it.
+	pub fn resource_path(&self, resources: &Path) -> PathBuf {
+		resources.join(self.key()).join(&self.name)
+	}
+}
+
+/// Fetches the files of an image into its resource directory.
+///
+/// Files fetched by an earlier build are reused, but are still checked
+/// against their declared digest and the lockfile.
+#[derive(Debug)]
+pub struct FetchBuildItem {
+	files: Files,
+}
+
+impl BuildItem for FetchBuildItem {
+	fn class(&self) -> Option<BuildClass> { Some(phase::PHASE_FETCH) }
+
+	fn register_dependencies(&self, _context: &mut BuildDependencyContext) -> error::Result<()> { Ok(()) }
+
+	fn build(&self, context: &mut BuildContext) -> error::Result<()> {
+		let resources = context.workspace.resource_filesystem(context.package);
+		for file in self.files.iter() {
+			let dir = Path::new("/").join(file.key());
+			let path = dir.join(&file.name);
+			let fetched = resources.exists(&path);
+
+			let data = if fetched {
+				resources.read(&path)
+			} else {
+				fetch(&file.src, context)
+			};
+			let data = try!(data.map_err(|e| Error::new(error::Stage::Build, e).with_message(format!("failed to fetch {}", file.src))));
+
+			let hash = digest::sha256(&data);
+			if let Some(ref expected) = file.sha256 {
+				if *expected != hash {
+					return Err(Error::message(error::Stage::Build, format!("{} has sha256 {}, but {} was declared", file.src, hash, expected)))
+				}
+			}
+			try!(context.lock_file(&file.src, &hash));
+
+			if !fetched {
+				try!(resources.create_dir_all(&dir).and_then(|_| resources.write(&path, &data))
+					.map_err(|e| Error::new(error::Stage::Build, e).with_message(format!("failed to store {}", file.src)))
+				);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Display for FetchBuildItem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		try!(f.write_str("fetch"));
+		for file in self.files.iter() {
+			try!(write!(f, " {}", file.name));
+		}
+		Ok(())
+	}
+}
+
+/// Downloads an http or https url, or reads any other source relative to
+/// the recipe.
+fn fetch(src: &str, context: &BuildContext) -> io::Result<Vec<u8>> {
+	if !src.starts_with("http://") && !src.starts_with("https://") {
+		return context.source_filesystem.read(Path::new(src.trim_left_matches("file://")))
+	}
+
+	let client = hyper::Client::new();
+	let mut response = try!(client.get(src).send().map_err(|e| io::Error::new(io::ErrorKind::Other, e)));
+	if !response.status.is_success() {
+		return Err(io::Error::new(io::ErrorKind::Other, format!("server responded with {}", response.status)))
+	}
+
+	let total = response.headers.get::<hyper::header::ContentLength>().map(|length| length.0);
+	let progress = |received| context.console.event(&Event::new(EventKind::DownloadProgress {
+		url: src,
+		received: received,
+		total: total,
+	}));
+
+	let mut data = Vec::new();
+	let mut buf = [0; 0x10000];
+	let mut reported = 0;
+	loop {
+		let len = match response.read(&mut buf) {
+			Ok(0) => break,
+			Ok(len) => len,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		};
+		data.extend_from_slice(&buf[..len]);
+
+		if data.len() - reported >= PROGRESS_INTERVAL {
+			reported = data.len();
+			progress(reported as u64);
+		}
+	}
+	progress(data.len() as u64);
+
+	Ok(data)
+}
+
+/// How many bytes are downloaded between progress events.
+const PROGRESS_INTERVAL: usize = 0x100000;
+
+fn file_name(src: &str) -> &str {
+	let src = src.split(|c| c == '?' || c == '#').next().unwrap_or(src);
+	src.rsplit('/').next().unwrap_or(src)
 }
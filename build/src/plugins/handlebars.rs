@@ -1,16 +1,40 @@
 extern crate handlebars;
 
 use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::env;
 use serde_value::{Value, DeserializerError};
 use typemap::Key;
-use self::handlebars::{Handlebars, TemplateError};
+use self::handlebars::{Handlebars, TemplateError, Helper, RenderContext, RenderError, Context as TemplateContext};
 use plugins::{Plugin, ImageConfigurationContext};
+use plugins::files::{FilesPlugin, Files};
+use parse::ImageKind;
+use command;
+use work::{self, Workspace};
 
-pub struct HandlebarsPlugin(());
+pub struct HandlebarsPlugin {
+	env_allow: Vec<String>,
+	workspace: PathBuf,
+}
 
 impl HandlebarsPlugin {
 	pub fn new() -> Self {
-		HandlebarsPlugin(())
+		HandlebarsPlugin {
+			env_allow: Vec::new(),
+			workspace: PathBuf::from(work::DEFAULT_ROOT),
+		}
+	}
+
+	/// Sets the workspace root `{{path build}}` refers to.
+	pub fn workspace<P: Into<PathBuf>>(mut self, root: P) -> Self {
+		self.workspace = root.into();
+		self
+	}
+
+	/// Allows templates to read the named environment variable through `{{env NAME}}`.
+	pub fn allow_env<S: Into<String>>(mut self, name: S) -> Self {
+		self.env_allow.push(name.into());
+		self
 	}
 }
 
@@ -19,12 +43,25 @@ impl Plugin for HandlebarsPlugin {
 		Some("handlebars")
 	}
 
+	fn config_keys(&self) -> &[&str] {
+		&["vars"]
+	}
+
 	fn configure_image(&self, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> {
-		if let Some(vars) = context.user_data.remove("vars") {
-			context.plugin_data.set::<Self>(Vars {
-				inner: try!(vars.deserialize_into()),
-			});
-		}
+		let vars = match context.user_data.remove("vars") {
+			Some(vars) => try!(vars.deserialize_into()),
+			None => BTreeMap::new(),
+		};
+
+		let workspace = try!(self.workspace.to_str().ok_or_else(||
+			DeserializerError::Syntax(format!("workspace {} is not valid UTF-8", self.workspace.display()))
+		));
+
+		context.plugin_data.set::<Self>(Vars {
+			inner: vars,
+			env: self.env_allow.iter().filter_map(|k| env::var(k).ok().map(|v| (k.clone(), v))).collect(),
+			workspace: workspace.to_owned(),
+		});
 
 		Ok(())
 	}
@@ -33,27 +70,151 @@ impl Plugin for HandlebarsPlugin {
 impl HandlebarsPlugin {
 	pub fn transform_string(str: String, context: &ImageConfigurationContext) -> Result<String, DeserializerError> {
 		let mut handlebars = Handlebars::new();
-		try!(handlebars.register_template_string("main", str)
-			.map_err(|e| match e {
-				TemplateError::UnclosedBraces(line, col) => DeserializerError::Syntax(format!("Unclosed brace at {}:{}", line, col)),
-				TemplateError::UnexpectedClosingBraces(line, col) => DeserializerError::Syntax(format!("Unexpected closing brace at {}:{}", line, col)),
-				TemplateError::MismatchingClosedHelper(line, col, ref expected, ref actual) => DeserializerError::Syntax(format!("Mismatched closing helper {} (expected {}) at {}:{}", actual, expected, line, col)),
-				TemplateError::UnclosedHelper(line, col, ref tag) => DeserializerError::Syntax(format!("Unclosed helper {} at {}:{}", tag, line, col)),
-			})
-		);
+		register_helpers(&mut handlebars, context);
 
 		#[derive(Debug, Serialize)]
 		struct Data<'a> {
 			#[serde(skip_serializing_if_none)]
 			vars: Option<&'a BTreeMap<String, Value>>,
-			package: &'a Package,
+			package: DataPackage<'a>,
+			path: BTreeMap<&'static str, String>,
+		}
+
+		#[derive(Debug, Serialize)]
+		struct DataPackage<'a> {
+			name: &'a str,
+			version: String,
 		}
 
 		let data = Data {
 			vars: context.plugin_data.get::<Self>().map(|v| &v.inner),
+			package: DataPackage {
+				name: &context.package.name,
+				version: context.package.version.to_string(),
+			},
+			path: paths(context),
 		};
 
-		handlebars.render("main", &data).map_err(|_| unimplemented!())
+		// Helper arguments may themselves contain expressions, such as
+		// `{{files "name_{{vars.version}}.deb"}}`, so render those first.
+		let str = try!(expand_nested(&str, |inner| render(&mut handlebars, "nested", inner.to_owned(), &data)));
+		render(&mut handlebars, "main", str, &data)
+	}
+}
+
+fn render<T: ::serde::Serialize>(handlebars: &mut Handlebars, name: &str, str: String, data: &T) -> Result<String, DeserializerError> {
+	try!(handlebars.register_template_string(name, str)
+		.map_err(|e| match e {
+			TemplateError::UnclosedBraces(line, col) => DeserializerError::Syntax(format!("Unclosed brace at {}:{}", line, col)),
+			TemplateError::UnexpectedClosingBraces(line, col) => DeserializerError::Syntax(format!("Unexpected closing brace at {}:{}", line, col)),
+			TemplateError::MismatchingClosedHelper(line, col, ref expected, ref actual) => DeserializerError::Syntax(format!("Mismatched closing helper {} (expected {}) at {}:{}", actual, expected, line, col)),
+			TemplateError::UnclosedHelper(line, col, ref tag) => DeserializerError::Syntax(format!("Unclosed helper {} at {}:{}", tag, line, col)),
+		})
+	);
+
+	handlebars.render(name, data).map_err(|e| DeserializerError::Syntax(format!("Template error: {}", e.desc)))
+}
+
+/// The paths available through `{{path name}}` and `path.name`.
+///
+/// Container commands see the image being built and its fetched files where
+/// `Command::run` mounts them, while host commands use their real location
+/// in the workspace.
+fn paths(context: &ImageConfigurationContext) -> BTreeMap<&'static str, String> {
+	let build = context.plugin_data.get::<HandlebarsPlugin>().map(|v| v.workspace.clone()).unwrap_or_else(|| work::DEFAULT_ROOT.to_owned());
+	let (target, resources) = match context.kind {
+		ImageKind::Container => (command::TARGET_PATH.to_owned(), command::RESOURCE_PATH.to_owned()),
+		ImageKind::Host => {
+			// host commands run inside the stage, so relative workspaces won't do
+			let workspace = Workspace::new(env::current_dir().map(|dir| dir.join(&build)).unwrap_or_else(|_| PathBuf::from(&build)));
+			(workspace.staging_dir(context.package).display().to_string(), workspace.resource_dir(context.package).display().to_string())
+		},
+	};
+
+	let mut paths = BTreeMap::new();
+	paths.insert("root", "/".to_owned());
+	paths.insert("target", target);
+	paths.insert("res", resources);
+	paths.insert("build", build);
+	paths
+}
+
+fn register_helpers(handlebars: &mut Handlebars, context: &ImageConfigurationContext) {
+	let files = context.plugin_data.get::<FilesPlugin>().cloned().unwrap_or_else(Files::default);
+	let env = context.plugin_data.get::<HandlebarsPlugin>().map(|v| v.env.clone()).unwrap_or_else(BTreeMap::new);
+	let paths = paths(context);
+
+	{
+		let files = files.clone();
+		let resources = PathBuf::from(&paths["res"]);
+		handlebars.register_helper("files", Box::new(move |_: &TemplateContext, h: &Helper, _: &Handlebars, rc: &mut RenderContext| -> Result<(), RenderError> {
+			let name = try!(param(h, 0));
+			let file = try!(files.find(name).ok_or_else(|| RenderError::new(format!("files: unknown file {}", name))));
+			try!(rc.writer.write_all(file.resource_path(&resources).display().to_string().as_bytes()));
+			Ok(())
+		}));
+	}
+
+	handlebars.register_helper("path", Box::new(move |_: &TemplateContext, h: &Helper, _: &Handlebars, rc: &mut RenderContext| -> Result<(), RenderError> {
+		let path = match try!(param(h, 0)) {
+			"mount" => format!("/mnt/{}", try!(param(h, 1))),
+			name => try!(paths.get(name).cloned().ok_or_else(|| RenderError::new(format!("path: unknown path {}", name)))),
+		};
+		try!(rc.writer.write_all(path.as_bytes()));
+		Ok(())
+	}));
+
+	handlebars.register_helper("env", Box::new(move |_: &TemplateContext, h: &Helper, _: &Handlebars, rc: &mut RenderContext| -> Result<(), RenderError> {
+		let name = try!(param(h, 0));
+		let value = try!(env.get(name).ok_or_else(|| RenderError::new(format!("env: {} is not set or not allowed", name))));
+		try!(rc.writer.write_all(value.as_bytes()));
+		Ok(())
+	}));
+
+	handlebars.register_helper("hash", Box::new(move |_: &TemplateContext, h: &Helper, _: &Handlebars, rc: &mut RenderContext| -> Result<(), RenderError> {
+		let name = try!(param(h, 0));
+		let file = try!(files.find(name).ok_or_else(|| RenderError::new(format!("hash: unknown file {}", name))));
+		let hash = try!(file.sha256.as_ref().ok_or_else(||
+			RenderError::new(format!("hash: {} has no sha256, and its contents aren't known until it is fetched", name))
+		));
+		try!(rc.writer.write_all(hash.as_bytes()));
+		Ok(())
+	}));
+}
+
+fn param<'a>(h: &'a Helper, index: usize) -> Result<&'a str, RenderError> {
+	h.param(index).map(|p| p.trim_matches('"'))
+		.ok_or_else(|| RenderError::new(format!("{}: missing argument {}", h.name(), index + 1)))
+}
+
+/// Renders every expression nested inside another one, innermost first, and
+/// leaves top-level expressions for the main render.
+fn expand_nested<F: FnMut(&str) -> Result<String, DeserializerError>>(str: &str, mut render: F) -> Result<String, DeserializerError> {
+	// The output so far, followed by each expression that is still open.
+	let mut stack = vec![String::with_capacity(str.len())];
+	let mut rest = str;
+
+	loop {
+		match (rest.find("{{"), rest.find("}}")) {
+			(Some(open), close) if close.map(|close| open < close).unwrap_or(true) => {
+				stack.last_mut().unwrap().push_str(&rest[..open]);
+				stack.push(String::from("{{"));
+				rest = &rest[open + 2..];
+			},
+			(_, Some(close)) => {
+				stack.last_mut().unwrap().push_str(&rest[..close + 2]);
+				rest = &rest[close + 2..];
+				if stack.len() > 1 {
+					let expression = stack.pop().unwrap();
+					let expression = if stack.len() > 1 { try!(render(&expression)) } else { expression };
+					stack.last_mut().unwrap().push_str(&expression);
+				}
+			},
+			(_, None) => {
+				stack.last_mut().unwrap().push_str(rest);
+				return Ok(stack.concat())
+			},
+		}
 	}
 }
 
@@ -64,4 +225,40 @@ impl Key for HandlebarsPlugin {
 #[derive(Clone, Debug, Default)]
 pub struct Vars {
 	inner: BTreeMap<String, Value>,
+	env: BTreeMap<String, String>,
+	workspace: String,
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_value::DeserializerError;
+	use super::expand_nested;
+
+	fn expand(str: &str) -> String {
+		expand_nested(str, |expression| Ok(format!("<{}>", &expression[2..expression.len() - 2]))).unwrap()
+	}
+
+	#[test]
+	fn top_level() {
+		assert_eq!(expand("a {{b}} c"), "a {{b}} c");
+		assert_eq!(expand("}} a {{b}} {{"), "}} a {{b}} {{");
+	}
+
+	#[test]
+	fn nested() {
+		assert_eq!(expand(r#"{{files "x_{{vars.version}}.deb"}}"#), r#"{{files "x_<vars.version>.deb"}}"#);
+		assert_eq!(expand("{{a {{b}} {{c}}}} {{d}}"), "{{a <b> <c>}} {{d}}");
+		assert_eq!(expand("{{a {{b {{c}}}}}}"), "{{a <b <c>>}}");
+	}
+
+	#[test]
+	fn unclosed() {
+		assert_eq!(expand("{{a {{b}} {{c"), "{{a <b> {{c");
+	}
+
+	#[test]
+	fn error() {
+		assert!(expand_nested("{{a {{b}}}}", |_| Err(DeserializerError::Syntax("b".into()))).is_err());
+		assert!(expand_nested("{{a}}", |_| Err(DeserializerError::Syntax("a".into()))).is_ok());
+	}
 }
@@ -1,8 +1,11 @@
 use serde_value::DeserializerError;
 
 use context::Context;
-use config::Package;
-use parse::{UserData, PluginData};
+use config::{Package, PackageQuery, Namespace};
+use error;
+use parse::{UserData, PluginData, ImageKind};
+use util::suggest;
+use phase::{self, PhaseRegistry};
 
 pub mod commands;
 pub mod files;
@@ -12,22 +15,35 @@ pub mod depends;
 
 pub struct ImageConfigurationContext<'a> {
 	pub root_package: &'a Package,
+	pub namespace: &'a Namespace,
 	pub package: &'a Package,
+	pub kind: ImageKind,
 	pub user_data: &'a mut UserData,
 	pub plugin_data: &'a mut PluginData,
 }
 
+pub struct ImageRequirementContext<'a> {
+	pub package: &'a Package,
+	pub plugin_data: &'a PluginData,
+	/// Packages the image needs, resolved along with those of every other image.
+	pub requirements: &'a mut Vec<PackageQuery>,
+}
+
 pub struct ImageDependencyContext<'a> {
 	pub context: &'a mut Context,
 	pub package: &'a Package,
+	pub kind: ImageKind,
 	pub plugin_data: &'a mut PluginData,
 }
 
 #[allow(unused_variables)]
 pub trait Plugin {
 	fn config_group(&self) -> Option<&str> { None }
+	fn config_keys(&self) -> &[&str] { &[] }
+	fn register_phases(&self, phases: &mut PhaseRegistry) { }
 	fn configure_image(&self, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> { Ok(()) }
-	fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> Result<(), ()> { Ok(()) }
+	fn image_requirements(&self, context: &mut ImageRequirementContext) { }
+	fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> error::Result<()> { Ok(()) }
 }
 
 pub struct Registry(Vec<Box<Plugin>>);
@@ -38,9 +54,15 @@ impl Registry {
 	}
 
 	pub fn register_builtins(&mut self) {
-		self.register_plugin(handlebars::HandlebarsPlugin::new());
-		self.register_plugin(commands::CommandsPlugin::new());
+		self.register_builtins_with(handlebars::HandlebarsPlugin::new());
+	}
+
+	/// Registers the builtin plugins, with a handlebars plugin configured by the caller.
+	pub fn register_builtins_with(&mut self, handlebars: handlebars::HandlebarsPlugin) {
+		self.register_plugin(handlebars);
+		// Files come before commands, which refer to them through `{{files}}`.
 		self.register_plugin(files::FilesPlugin::new());
+		self.register_plugin(commands::CommandsPlugin::new());
 		self.register_plugin(build::BuildPlugin::new());
 		self.register_plugin(depends::DependsPlugin::new());
 	}
@@ -49,31 +71,64 @@ impl Registry {
 		self.0.push(Box::new(p));
 	}
 
+	pub fn register_phases(&self, phases: &mut PhaseRegistry) {
+		for plugin in &self.0 {
+			plugin.register_phases(phases);
+		}
+	}
+
+	pub fn config_groups(&self) -> Vec<&str> {
+		let mut groups: Vec<_> = self.0.iter().filter_map(|p| p.config_group()).collect();
+		groups.sort();
+		groups.dedup();
+		groups
+	}
+
 	pub fn configure_image<S: AsRef<str>, I: IntoIterator<Item=S>>(&self, plugins: I, context: &mut ImageConfigurationContext) -> Result<(), DeserializerError> {
 		let plugins = plugins.into_iter().map(|s| s.as_ref().to_owned()).collect::<Vec<_>>();
 
-		for plugin in &self.0 {
-			if let Some(config_group) = plugin.config_group() {
-				if plugins.iter().any(|s| s == config_group) {
-					try!(plugin.configure_image(&mut *context));
-				}
-			}
+		let groups = self.config_groups();
+		if let Some(unknown) = plugins.iter().find(|s| !groups.contains(&&s[..])) {
+			return Err(DeserializerError::Syntax(format!("unknown plugin group `{}` (registered: {})", unknown, groups.join(", "))))
+		}
+
+		let enabled = |plugin: &Box<Plugin>| plugin.config_group().map(|g| plugins.iter().any(|s| s == g)).unwrap_or(false);
+
+		for plugin in self.0.iter().filter(|p| enabled(p)) {
+			try!(plugin.configure_image(&mut *context));
 		}
 
-		// TODO: warn on unknown plugins
-		// return Err(::serde::de::Error::unknown_field(field)),
+		if let Some(key) = context.user_data.keys().next() {
+			let mut message = format!("image `{}`: unknown key `{}`", context.package.name, key);
+
+			let known = self.0.iter().filter(|p| enabled(p)).flat_map(|p| p.config_keys().iter().cloned());
+			if let Some(suggestion) = suggest::closest(key, known) {
+				message.push_str(&format!(", did you mean `{}`?", suggestion));
+			}
+
+			if let Some(group) = self.0.iter().filter(|p| !enabled(p) && p.config_keys().contains(&&key[..])).filter_map(|p| p.config_group()).next() {
+				message.push_str(&format!(" (handled by the `{}` plugin group, which is not enabled in package.plugins)", group));
+			}
+
+			return Err(DeserializerError::Syntax(message))
+		}
 
 		Ok(())
 	}
 
-	pub fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> Result<(), ()> {
+	pub fn image_requirements(&self, context: &mut ImageRequirementContext) {
+		for plugin in &self.0 {
+			plugin.image_requirements(&mut *context);
+		}
+	}
+
+	pub fn configure_image_dependencies(&self, context: &mut ImageDependencyContext) -> error::Result<()> {
 		for plugin in &self.0 {
-			try!(plugin.configure_image_dependencies(&mut *context));
+			try!(plugin.configure_image_dependencies(&mut *context).map_err(|e| e.with_package(context.package)));
 		}
 
-		let image_id = context.context.register_build_item(context.package, ::build::NullBuildItem::new("image", Some(::build::BUILD_CLASS_IMAGE.id())));
-		let stage_id = context.context.register_build_item(context.package, ::build::NullBuildItem::new("stage", Some(::build::BUILD_CLASS_STAGE.id())));
-		context.context.dependency_graph.link(image_id, stage_id);
+		context.context.register_build_item(context.package, ::build::NullBuildItem::new("image", Some(phase::PHASE_IMAGE)));
+		context.context.register_build_item(context.package, ::build::NullBuildItem::new("stage", Some(phase::PHASE_STAGE)));
 
 		Ok(())
 	}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use config::{Package, PackageQuery};
+use lock::{Lockfile, LockMode};
+use error::{Error, Stage, Result};
+
+#[derive(Debug, Clone)]
+pub struct Requirement {
+	pub query: PackageQuery,
+	/// The package that asked for this requirement, or `None` for the root.
+	pub required_by: Option<Package>,
+}
+
+/// Picks one version of each required package that satisfies every requirement on it.
+pub struct Resolver<'a> {
+	available: Vec<&'a Package>,
+	requirements: BTreeMap<String, Vec<Requirement>>,
+}
+
+impl<'a> Resolver<'a> {
+	pub fn new<I: IntoIterator<Item=&'a Package>>(available: I) -> Self {
+		Resolver {
+			available: available.into_iter().collect(),
+			requirements: BTreeMap::new(),
+		}
+	}
+
+	pub fn require(&mut self, query: PackageQuery, required_by: Option<&Package>) {
+		self.requirements.entry(query.name.clone()).or_insert_with(Vec::new).push(Requirement {
+			query: query,
+			required_by: required_by.cloned(),
+		});
+	}
+
+	/// Resolves every requirement, preferring the version pinned in `lock`
+	/// and otherwise the highest compatible one, then updates `lock` to match.
+	pub fn resolve(&self, lock: &mut Lockfile, mode: LockMode) -> Result<Vec<Package>> {
+		let mut resolved = Vec::new();
+
+		for (name, requirements) in &self.requirements {
+			let candidates: Vec<&Package> = self.available.iter().cloned().filter(|p| &p.name == name).collect();
+			let compatible = |p: &&Package| requirements.iter().all(|r| r.query.version_req.matches(&p.version));
+
+			let locked = lock.package(name).and_then(|v| candidates.iter().cloned().filter(&compatible).find(|p| &p.version == v));
+			let package = match locked.or_else(|| candidates.iter().cloned().filter(&compatible).max_by_key(|p| &p.version)) {
+				Some(package) => package,
+				None => return Err(Error::message(Stage::Dependencies, explain_conflict(name, requirements, &candidates))),
+			};
+
+			try!(lock.lock_package(package, mode));
+			resolved.push(package.clone());
+		}
+
+		try!(lock.retain_packages(&resolved, mode));
+
+		Ok(resolved)
+	}
+}
+
+fn explain_conflict(name: &str, requirements: &[Requirement], candidates: &[&Package]) -> String {
+	let mut out = String::new();
+
+	if candidates.is_empty() {
+		let _ = write!(out, "no package named {} is available", name);
+	} else {
+		let _ = write!(out, "no version of {} satisfies every requirement on it", name);
+	}
+
+	for requirement in requirements {
+		let _ = match requirement.required_by {
+			Some(ref by) => write!(out, "\n  {}-{} requires {} {}", by.name, by.version, name, requirement.query.version_req),
+			None => write!(out, "\n  the build target requires {} {}", name, requirement.query.version_req),
+		};
+	}
+
+	if !candidates.is_empty() {
+		let versions: Vec<String> = candidates.iter().map(|p| p.version.to_string()).collect();
+		let _ = write!(out, "\n  available versions: {}", versions.join(", "));
+	}
+
+	out
+}
@@ -0,0 +1,88 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use dependencies::DependencyGraph;
+use Id;
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+	/// The maximum number of build items run at once.
+	pub jobs: usize,
+	/// Whether to keep building items that don't depend on a failed item,
+	/// rather than stopping as soon as running items have drained.
+	pub keep_going: bool,
+}
+
+impl Default for BuildOptions {
+	fn default() -> Self {
+		BuildOptions {
+			jobs: 1,
+			keep_going: false,
+		}
+	}
+}
+
+/// Tracks which of a set of build items are ready to run.
+///
+/// An item becomes ready once every one of its dependencies within the set
+/// has completed.
+pub struct Schedule {
+	waiting: HashMap<Id, usize>,
+	dependents: HashMap<Id, Vec<Id>>,
+	ready: VecDeque<Id>,
+}
+
+impl Schedule {
+	/// Schedules `items`, which must be in dependency order. Items in
+	/// `completed` are considered already built.
+	pub fn new(graph: &DependencyGraph, items: &[Id], completed: &HashSet<Id>) -> Self {
+		let pending: HashSet<Id> = items.iter().cloned().filter(|id| !completed.contains(id)).collect();
+		let mut schedule = Schedule {
+			waiting: HashMap::new(),
+			dependents: HashMap::new(),
+			ready: VecDeque::new(),
+		};
+
+		for &id in items.iter().filter(|id| pending.contains(id)) {
+			let deps: Vec<Id> = graph.dependencies(id).iter().cloned().filter(|dep| pending.contains(dep)).collect();
+			for &dep in &deps {
+				schedule.dependents.entry(dep).or_insert_with(Vec::new).push(id);
+			}
+
+			if deps.is_empty() {
+				schedule.ready.push_back(id);
+			} else {
+				schedule.waiting.insert(id, deps.len());
+			}
+		}
+
+		schedule
+	}
+
+	pub fn next_ready(&mut self) -> Option<Id> {
+		self.ready.pop_front()
+	}
+
+	/// Marks `id` as built, readying any dependents that were only waiting on it.
+	pub fn complete(&mut self, id: Id) {
+		for dependent in self.dependents.remove(&id).unwrap_or_else(Vec::new) {
+			let ready = match self.waiting.get_mut(&dependent) {
+				Some(count) => {
+					*count -= 1;
+					*count == 0
+				},
+				None => false,
+			};
+
+			if ready {
+				self.waiting.remove(&dependent);
+				self.ready.push_back(dependent);
+			}
+		}
+	}
+
+	/// The number of items that haven't been started, whether still waiting
+	/// on dependencies or ready but never taken. Once nothing is running,
+	/// these are the items blocked by a failure.
+	pub fn blocked(&self) -> usize {
+		self.waiting.len() + self.ready.len()
+	}
+}
@@ -0,0 +1,14 @@
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+
+/// The SHA-256 digest of `data`, as lowercase hex.
+pub fn sha256(data: &[u8]) -> String {
+	let mut hasher = Sha256::new();
+	hasher.input(data);
+	hasher.result_str()
+}
+
+/// Whether `s` is a hex SHA-256 digest, in either case.
+pub fn is_sha256(s: &str) -> bool {
+	s.len() == 64 && s.chars().all(|c| c.is_digit(16))
+}
@@ -1 +1,3 @@
-pub mod static_id;
+pub mod digest;
+pub mod suggest;
+pub mod text;
@@ -0,0 +1,29 @@
+/// Levenshtein edit distance between two strings.
+pub fn distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut row: Vec<usize> = (0..b.len() + 1).collect();
+
+	for (i, ca) in a.chars().enumerate() {
+		let mut prev = row[0];
+		row[0] = i + 1;
+		for (j, &cb) in b.iter().enumerate() {
+			let cost = if ca == cb { 0 } else { 1 };
+			let next = ::std::cmp::min(::std::cmp::min(row[j + 1] + 1, row[j] + 1), prev + cost);
+			prev = row[j + 1];
+			row[j + 1] = next;
+		}
+	}
+
+	row[b.len()]
+}
+
+/// Finds the candidate closest to `name`, if any is close enough to be a plausible typo.
+pub fn closest<'a, S: AsRef<str> + ?Sized + 'a, I: IntoIterator<Item=&'a S>>(name: &str, candidates: I) -> Option<&'a str> {
+	let threshold = ::std::cmp::max(name.len() / 3, 1);
+
+	candidates.into_iter()
+		.map(|c| (distance(name, c.as_ref()), c.as_ref()))
+		.filter(|&(d, _)| d <= threshold)
+		.min_by_key(|&(d, _)| d)
+		.map(|(_, c)| c)
+}
@@ -0,0 +1,35 @@
+/// Removes the leading whitespace common to every non-blank line of `s`.
+///
+/// Whitespace is compared literally, so a tab only matches a tab. Lines made
+/// up only of whitespace don't affect the common prefix and come out empty.
+pub fn dedent(s: &str) -> String {
+	let indent = s.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(|line| &line[..line.len() - line.trim_left().len()])
+		.fold(None, |prefix: Option<&str>, indent| Some(match prefix {
+			Some(prefix) => common_prefix(prefix, indent),
+			None => indent,
+		}))
+		.unwrap_or("");
+
+	let mut out = String::with_capacity(s.len());
+	for (i, line) in s.split('\n').enumerate() {
+		if i > 0 {
+			out.push('\n');
+		}
+		if line.trim().is_empty() {
+			continue
+		}
+		out.push_str(if line.starts_with(indent) { &line[indent.len()..] } else { line });
+	}
+
+	out
+}
+
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+	let len = a.char_indices().zip(b.chars())
+		.find(|&((_, ca), cb)| ca != cb)
+		.map(|((i, _), _)| i)
+		.unwrap_or(::std::cmp::min(a.len(), b.len()));
+	&a[..len]
+}
@@ -1,19 +1,92 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::io;
 use config::Package;
-use filesystem::FilesystemObject;
+use fs::{Filesystem, OsFilesystem, SubFilesystem};
 
+/// The workspace root used when none is given.
+pub const DEFAULT_ROOT: &'static str = "build";
+
+/// The build output directory, laid out as `<root>/<image>/{stage,res,stamp,workdir,log}`.
 pub struct Workspace {
 	root: PathBuf,
+	filesystem: Box<Filesystem>,
 }
 
 impl Workspace {
+	pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+		Workspace::with_filesystem(root, OsFilesystem)
+	}
+
+	pub fn with_filesystem<P: Into<PathBuf>, F: Filesystem + 'static>(root: P, filesystem: F) -> Self {
+		Workspace {
+			root: root.into(),
+			filesystem: Box::new(filesystem),
+		}
+	}
+
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+
+	pub fn filesystem(&self) -> &Filesystem {
+		&*self.filesystem
+	}
+
+	pub fn image_dir(&self, package: &Package) -> PathBuf {
+		self.root.join(format!("{}-{}", package.name, package.version))
+	}
+
+	/// The root of the image as it is being built.
 	pub fn staging_dir(&self, package: &Package) -> PathBuf {
-		let mut root = self.root.clone();
-		root.push(format!("{}-{}", package.name, package.version));
-		root
+		self.image_dir(package).join("stage")
+	}
+
+	/// Fetched files, mounted into container commands at `command::RESOURCE_PATH`.
+	pub fn resource_dir(&self, package: &Package) -> PathBuf {
+		self.image_dir(package).join("res")
+	}
+
+	pub fn stamp_dir(&self, package: &Package) -> PathBuf {
+		self.image_dir(package).join("stamp")
+	}
+
+	/// Scratch space for a single build step, such as an overlay workdir.
+	pub fn work_dir<S: AsRef<str>>(&self, package: &Package, id: S) -> PathBuf {
+		self.image_dir(package).join("workdir").join(id.as_ref())
+	}
+
+	/// The log file of a single build item.
+	pub fn log_path<S: AsRef<str>>(&self, package: &Package, item: S) -> PathBuf {
+		let name: String = item.as_ref().chars()
+			.map(|c| if c.is_alphanumeric() || c == '.' || c == '_' { c } else { '-' })
+			.collect();
+		self.image_dir(package).join("log").join(format!("{}.log", name.trim_matches('-')))
+	}
+
+	pub fn staging_filesystem(&self, package: &Package) -> SubFilesystem {
+		SubFilesystem::new(&*self.filesystem, self.staging_dir(package))
+	}
+
+	pub fn resource_filesystem(&self, package: &Package) -> SubFilesystem {
+		SubFilesystem::new(&*self.filesystem, self.resource_dir(package))
+	}
+
+	/// Creates the directories of an image.
+	pub fn prepare(&self, package: &Package) -> io::Result<()> {
+		for dir in &[self.staging_dir(package), self.resource_dir(package), self.stamp_dir(package), self.image_dir(package).join("workdir"), self.image_dir(package).join("log")] {
+			try!(self.filesystem.create_dir_all(dir));
+		}
+
+		Ok(())
+	}
+
+	pub fn is_stamped<S: AsRef<str>>(&self, package: &Package, stamp: S) -> bool {
+		self.filesystem.exists(&self.stamp_dir(package).join(stamp.as_ref()))
 	}
 
-	pub fn staging_filesystem(&self, package: &Package) -> &FilesystemObject {
-		unimplemented!()
+	pub fn stamp<S: AsRef<str>>(&self, package: &Package, stamp: S) -> io::Result<()> {
+		let dir = self.stamp_dir(package);
+		try!(self.filesystem.create_dir_all(&dir));
+		self.filesystem.write(&dir.join(stamp.as_ref()), &[])
 	}
 }
@@ -0,0 +1,119 @@
+extern crate encage_build;
+
+use std::env;
+use std::fs;
+use encage_build::console::{DefaultConsole, LogLevel};
+use encage_build::context::Context;
+use encage_build::fs::OsFilesystem;
+use encage_build::parse;
+use encage_build::plugins::Registry;
+use encage_build::plugins::handlebars::HandlebarsPlugin;
+use encage_build::schedule::BuildOptions;
+use encage_build::work::Workspace;
+
+/// Builds a host image layered on another into a real workspace.
+#[test]
+fn build_two_images() {
+	let root = env::temp_dir().join("encage-build-test-build");
+	let _ = fs::remove_dir_all(&root);
+
+	let data = b"
+[package]
+name = \"encage.e2e\"
+version = \"0.0.1\"
+plugins = [\"base\", \"handlebars\"]
+
+[[image]]
+name = \".base\"
+type = \"host\"
+commands = \"echo base > base.txt\"
+
+[[image]]
+name = \".app\"
+type = \"host\"
+depends = \".base\"
+commands = \"cat {{path build}}/encage.e2e.base-0.0.1/stage/base.txt > app.txt\"
+";
+
+	let mut plugins = Registry::new();
+	plugins.register_builtins_with(HandlebarsPlugin::new().workspace(&root));
+	let recipe = parse::parse(&mut &data[..], &plugins).expect("parse failed");
+	let app = recipe.images[1].package.clone();
+
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	context.load_recipe(&plugins, recipe).expect("load failed");
+	context.resolve_dependencies(&plugins).expect("resolve failed");
+
+	let workspace = Workspace::new(&root);
+	context.build(&app, &workspace, &BuildOptions::default(), &OsFilesystem).expect("build failed");
+
+	let app_txt = fs::File::open(workspace.staging_dir(&app).join("app.txt")).and_then(|mut f| {
+		use std::io::Read;
+
+		let mut s = String::new();
+		f.read_to_string(&mut s).map(|_| s)
+	});
+	assert_eq!(app_txt.expect("app.txt missing"), "base\n");
+	assert!(fs::read_dir(workspace.image_dir(&app).join("log")).unwrap().count() > 0);
+
+	let image = context.image_dependency(&app).unwrap();
+	assert!(context.is_completed(image));
+
+	// Everything is already built, so nothing runs again.
+	fs::remove_file(workspace.staging_dir(&app).join("app.txt")).unwrap();
+	context.build(&app, &workspace, &BuildOptions::default(), &OsFilesystem).expect("rebuild failed");
+	assert!(!workspace.staging_dir(&app).join("app.txt").exists());
+
+	let _ = fs::remove_dir_all(&root);
+}
+
+/// Fetches a file into a host image and reads it from a command, which sees
+/// the real resource and staging directories.
+#[test]
+fn host_files() {
+	let root = env::temp_dir().join("encage-build-test-files");
+	let _ = fs::remove_dir_all(&root);
+	fs::create_dir_all(&root).unwrap();
+	let src = root.join("hello.txt");
+	fs::File::create(&src).and_then(|mut f| {
+		use std::io::Write;
+
+		f.write_all(b"hello\n")
+	}).unwrap();
+
+	let data = format!("
+[package]
+name = \"encage.files\"
+version = \"0.0.1\"
+plugins = [\"base\", \"handlebars\"]
+
+[[image]]
+name = \".host\"
+type = \"host\"
+files = [ {{ src = \"{}\" }} ]
+commands = \"cat {{{{files \\\"hello.txt\\\"}}}} > copy.txt && echo {{{{path target}}}} > target.txt\"
+", src.display());
+
+	let mut plugins = Registry::new();
+	plugins.register_builtins_with(HandlebarsPlugin::new().workspace(root.join("work")));
+	let recipe = parse::parse(&mut data.as_bytes(), &plugins).expect("parse failed");
+	let host = recipe.images[0].package.clone();
+
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	context.load_recipe(&plugins, recipe).expect("load failed");
+	context.resolve_dependencies(&plugins).expect("resolve failed");
+
+	let workspace = Workspace::new(root.join("work"));
+	context.build(&host, &workspace, &BuildOptions::default(), &OsFilesystem).expect("build failed");
+
+	let read = |name| fs::File::open(workspace.staging_dir(&host).join(name)).and_then(|mut f| {
+		use std::io::Read;
+
+		let mut s = String::new();
+		f.read_to_string(&mut s).map(|_| s)
+	}).expect("output missing");
+	assert_eq!(read("copy.txt"), "hello\n");
+	assert_eq!(read("target.txt"), format!("{}\n", workspace.staging_dir(&host).display()));
+
+	let _ = fs::remove_dir_all(&root);
+}
@@ -0,0 +1,31 @@
+extern crate encage_build;
+
+use std::path::{Path, PathBuf};
+use encage_build::command::{Command, Root, Limits, Network};
+
+#[test]
+fn runtime_args() {
+	let root = Root {
+		stage: Path::new("/build/app-0.1.0/stage"),
+		resources: PathBuf::from("/build/app-0.1.0/res"),
+		layers: vec![PathBuf::from("/build/base-0.1.0/stage")],
+		overlay_work: PathBuf::from("/build/app-0.1.0/workdir"),
+		network: Network::None,
+		hostname: None,
+		capabilities: Vec::new(),
+		privileged: false,
+		devices: vec!["/dev/fuse".into()],
+	};
+
+	let args = Command::Exec(vec!["true".into()]).runtime_args(&root, &Limits::default());
+	let args = args.iter().map(|arg| arg.to_str().unwrap()).collect::<Vec<_>>();
+	assert_eq!(args, [
+		"exec", "--network", "none",
+		"--device", "/dev/fuse",
+		"--mount", "/build/app-0.1.0/res:/mnt/res",
+		"--mount", "/build/app-0.1.0/stage:/mnt/target",
+		"--lower", "/build/base-0.1.0/stage",
+		"--work", "/build/app-0.1.0/workdir",
+		"/build/app-0.1.0/stage", "true",
+	]);
+}
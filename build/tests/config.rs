@@ -0,0 +1,43 @@
+extern crate encage_build;
+extern crate semver;
+
+use encage_build::config::{Package, Namespace};
+
+fn package(name: &str) -> Package {
+	Package {
+		name: name.to_owned(),
+		version: semver::Version::parse("0.1.0").unwrap(),
+	}
+}
+
+#[test]
+fn absolute_name() {
+	let hello = package("mx.arcn.hello");
+
+	assert_eq!(hello.absolute_name(&"org.example.base").unwrap(), "org.example.base");
+	assert_eq!(hello.absolute_name(&".arch").unwrap(), "mx.arcn.hello.arch");
+	assert_eq!(hello.absolute_name(&"..arch").unwrap(), "mx.arcn.arch");
+	assert_eq!(hello.absolute_name(&"...arch").unwrap(), "mx.arch");
+
+	let err = hello.absolute_name(&"....arch").err().expect("resolved above the top level");
+	assert!(err.to_string().contains("refers above the top-level namespace of mx.arcn.hello"), "{}", err);
+
+	assert!(hello.absolute_name(&"..").is_err());
+}
+
+#[test]
+fn namespace_resolve() {
+	let mut namespace = Namespace::new(package("mx.arcn.hello"));
+	namespace.aliases.insert("busybox".into(), "org.busybox.static".into());
+
+	assert_eq!(namespace.resolve(".arch").unwrap(), "mx.arcn.hello.arch");
+	assert_eq!(namespace.resolve("..arch").unwrap(), "mx.arcn.arch");
+	assert_eq!(namespace.resolve("busybox").unwrap(), "org.busybox.static");
+	assert_eq!(namespace.resolve("busybox.root").unwrap(), "org.busybox.static.root");
+	assert_eq!(namespace.resolve("busyboxes.root").unwrap(), "busyboxes.root");
+	assert_eq!(namespace.resolve("org.example").unwrap(), "org.example");
+	assert!(namespace.resolve("....arch").is_err());
+
+	let resolved = namespace.resolve_package("..arch").unwrap();
+	assert_eq!(resolved, package("mx.arcn.arch"));
+}
@@ -0,0 +1,29 @@
+extern crate encage_build;
+
+use encage_build::console::{Console, JsonConsole, LogLevel, Event, EventKind, Stream};
+
+#[test]
+fn json_escaping() {
+	let console = JsonConsole::new(Vec::new(), LogLevel::Debug);
+	console.log(LogLevel::Note, format_args!("say \"hi\"\\\n\tbell\x07 ünïcode"));
+	console.event(&Event {
+		source: Some("commands"),
+		kind: EventKind::CommandOutput {
+			stream: Stream::Stderr,
+			line: "\r\x1b[0m",
+		},
+	});
+	console.event(&Event::new(EventKind::DownloadProgress {
+		url: "http://example.com/a.tar",
+		received: 1024,
+		total: None,
+	}));
+	console.log(LogLevel::Trace, format_args!("filtered"));
+
+	let output = String::from_utf8(console.into_inner()).unwrap();
+	assert_eq!(output.lines().collect::<Vec<_>>(), [
+		r#"{"event":"log","level":"note","message":"say \"hi\"\\\n\tbell\u0007 ünïcode"}"#,
+		r#"{"event":"output","source":"commands","stream":"stderr","line":"\r\u001b[0m"}"#,
+		r#"{"event":"download","source":null,"url":"http://example.com/a.tar","received":1024,"total":null}"#,
+	]);
+}
@@ -0,0 +1,106 @@
+extern crate encage_build;
+extern crate semver;
+
+use encage_build::config::Package;
+use encage_build::console::{DefaultConsole, LogLevel};
+use encage_build::context::Context;
+use encage_build::plugins::Registry;
+
+/// Loads a recipe made of `images` into `context`.
+fn load(context: &mut Context, images: &str) -> Result<(), String> {
+	let data = format!("[package]\nname = \"encage.kinds\"\nversion = \"0.0.1\"\nplugins = [\"base\"]\n{}", images);
+
+	let mut plugins = Registry::new();
+	plugins.register_builtins();
+	let recipe = try!(encage_build::parse::parse(&mut data.as_bytes(), &plugins).map_err(|e| e.to_string()));
+	try!(context.load_recipe(&plugins, recipe).map_err(|e| e.to_string()));
+	context.resolve_dependencies(&plugins).map_err(|e| e.to_string())
+}
+
+#[test]
+fn host_layer_rejected() {
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	let err = load(&mut context, "
+[[image]]
+name = \".tools\"
+type = \"host\"
+
+[[image]]
+name = \".app\"
+depends = \".tools\"
+").err().expect("container was layered on a host image");
+
+	assert!(err.contains("cannot use host image encage.kinds.tools"), "{}", err);
+}
+
+#[test]
+fn container_layers_accepted() {
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	load(&mut context, "
+[[image]]
+name = \".base\"
+
+[[image]]
+name = \".app\"
+depends = \".base\"
+
+[[image]]
+name = \".deploy\"
+type = \"host\"
+depends = \".app\"
+").expect("load failed");
+
+	let package = |name: &str| Package {
+		name: name.to_owned(),
+		version: semver::Version::parse("0.0.1").unwrap(),
+	};
+	assert_eq!(context.layers(&package("encage.kinds.deploy")), [package("encage.kinds.app"), package("encage.kinds.base")]);
+}
+
+#[test]
+fn unknown_kind_rejected() {
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	context.packages.insert(Package {
+		name: "org.example.base".into(),
+		version: semver::Version::parse("1.0.0").unwrap(),
+	});
+
+	let err = load(&mut context, "
+[[image]]
+name = \".app\"
+depends = \"org.example.base\"
+").err().expect("layered on an image of unknown type");
+
+	assert!(err.contains("org.example.base-1.0.0 has no loaded recipe"), "{}", err);
+}
+
+#[test]
+fn relative_version_req() {
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	load(&mut context, "
+[[image]]
+name = \".base\"
+
+[[image]]
+name = \".app\"
+depends = \".base@^0.0.1\"
+").expect("load failed");
+
+	let package = |name: &str| Package {
+		name: name.to_owned(),
+		version: semver::Version::parse("0.0.1").unwrap(),
+	};
+	assert_eq!(context.layers(&package("encage.kinds.app")), [package("encage.kinds.base")]);
+
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	let err = load(&mut context, "
+[[image]]
+name = \".base\"
+
+[[image]]
+name = \".app\"
+depends = \".base@>=1\"
+").err().expect("resolved a sibling image of another version");
+
+	assert!(err.contains("dependency `.base@>=1` (resolved to encage.kinds.base"), "{}", err);
+}
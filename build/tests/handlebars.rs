@@ -0,0 +1,61 @@
+extern crate encage_build;
+
+use encage_build::plugins::Registry;
+use encage_build::plugins::commands::CommandsPlugin;
+use encage_build::plugins::handlebars::HandlebarsPlugin;
+use encage_build::util::digest;
+
+const EMPTY_SHA256: &'static str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Parses a single image and returns its first shell command.
+fn render(image: &str) -> Result<String, String> {
+	let data = format!("[package]\nname = \"encage.templates\"\nversion = \"0.0.1\"\nplugins = [\"base\", \"handlebars\"]\n\n[[image]]\nname = \".test\"\n{}", image);
+
+	let mut plugins = Registry::new();
+	plugins.register_builtins_with(HandlebarsPlugin::new().workspace("/var/lib/encage"));
+	encage_build::parse::parse(&mut data.as_bytes(), &plugins)
+		.map(|recipe| recipe.images[0].plugin_data.get::<CommandsPlugin>().expect("no commands").commands[0].0.args()[2].to_owned())
+		.map_err(|e| e.to_string())
+}
+
+#[test]
+fn helpers() {
+	let command = render(&format!(r#"
+vars = {{ version = "1.0" }}
+files = [ {{ src = "http://example.com/a_{{{{vars.version}}}}.tar", sha256 = "{}" }} ]
+commands = "tar -xf {{{{files \"a_{{{{vars.version}}}}.tar\"}}}} # {{{{hash \"a_1.0.tar\"}}}} {{{{path build}}}} {{{{path.target}}}}"
+"#, EMPTY_SHA256.to_uppercase())).expect("render failed");
+
+	let key = &digest::sha256(b"http://example.com/a_1.0.tar")[..16];
+	assert_eq!(command, format!("tar -xf /mnt/res/{}/a_1.0.tar # {} /var/lib/encage /mnt/target", key, EMPTY_SHA256));
+}
+
+#[test]
+fn hash_unknown_file() {
+	let err = render(r#"commands = "echo {{hash \"missing.tar\"}}""#).err().expect("render succeeded");
+	assert!(err.contains("unknown file missing.tar"));
+
+	let err = render(r#"
+files = [ { src = "http://example.com/a.tar" } ]
+commands = "echo {{hash \"a.tar\"}}"
+"#).err().expect("render succeeded");
+	assert!(err.contains("a.tar has no sha256"));
+
+	let err = render(r#"
+files = [ { src = "http://example.com/a.tar", sha256 = "abc" } ]
+commands = "true"
+"#).err().expect("parse succeeded");
+	assert!(err.contains("invalid sha256"));
+}
+
+#[test]
+fn host_paths() {
+	let command = render(r#"
+type = "host"
+files = [ { src = "http://example.com/a.tar" } ]
+commands = "tar -xf {{files \"a.tar\"}} -C {{path target}}"
+"#).expect("render failed");
+
+	let key = &digest::sha256(b"http://example.com/a.tar")[..16];
+	assert_eq!(command, format!("tar -xf /var/lib/encage/encage.templates.test-0.0.1/res/{}/a.tar -C /var/lib/encage/encage.templates.test-0.0.1/stage", key));
+}
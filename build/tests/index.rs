@@ -0,0 +1,74 @@
+extern crate encage_build;
+extern crate semver;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use encage_build::config::Package;
+use encage_build::console::{DefaultConsole, LogLevel};
+use encage_build::context::Context;
+use encage_build::index::PackageIndex;
+use encage_build::parse::{self, ImageKind};
+use encage_build::plugins::Registry;
+
+fn package(name: &str, version: &str) -> Package {
+	Package {
+		name: name.to_owned(),
+		version: semver::Version::parse(version).unwrap(),
+	}
+}
+
+/// Resolves a recipe whose layers only exist as recipes on the search path.
+#[test]
+fn load_indexed_recipes() {
+	let dir = env::temp_dir().join("encage-build-test-index");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	let recipes = [
+		("base-1.0.toml", "[package]\nname = \"org.example.base\"\nversion = \"1.0.0\"\nplugins = [\"base\"]\n\n[[image]]\nname = \".busybox\"\n"),
+		("base-1.2.toml", "[package]\nname = \"org.example.base\"\nversion = \"1.2.0\"\nplugins = [\"base\"]\n\n[[image]]\nname = \".busybox\"\ndepends = \"org.example.libc.root\"\n"),
+		("libc.toml", "[package]\nname = \"org.example.libc\"\nversion = \"0.1.0\"\nplugins = [\"base\"]\n\n[[image]]\nname = \".root\"\n"),
+		("notes.txt", "not a recipe"),
+	];
+	for &(name, data) in &recipes {
+		File::create(dir.join(name)).and_then(|mut f| f.write_all(data.as_bytes())).unwrap();
+	}
+
+	let mut index = PackageIndex::new();
+	index.add_dir(&dir).expect("indexing failed");
+	assert_eq!(index.packages().cloned().collect::<Vec<_>>(), [
+		package("org.example.base.busybox", "1.0.0"),
+		package("org.example.base.busybox", "1.2.0"),
+		package("org.example.libc.root", "0.1.0"),
+	]);
+
+	let data = b"
+[package]
+name = \"org.example.app\"
+version = \"0.0.1\"
+plugins = [\"base\"]
+
+[[image]]
+name = \".app\"
+depends = \"org.example.base.busybox@^1.1\"
+";
+
+	let mut plugins = Registry::new();
+	plugins.register_builtins();
+	let recipe = parse::parse(&mut &data[..], &plugins).expect("parse failed");
+
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	context.load_index(index);
+	context.load_recipe(&plugins, recipe).expect("load failed");
+	context.resolve_dependencies(&plugins).expect("resolve failed");
+
+	assert_eq!(context.layers(&package("org.example.app.app", "0.0.1")), [
+		package("org.example.base.busybox", "1.2.0"),
+		package("org.example.libc.root", "0.1.0"),
+	]);
+	assert_eq!(context.image_kind(&package("org.example.libc.root", "0.1.0")), Some(ImageKind::Container));
+	assert_eq!(context.image_kind(&package("org.example.base.busybox", "1.0.0")), None);
+
+	let _ = fs::remove_dir_all(&dir);
+}
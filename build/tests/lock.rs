@@ -0,0 +1,71 @@
+extern crate encage_build;
+extern crate semver;
+
+use encage_build::config::{Package, PackageQuery};
+use encage_build::lock::{Lockfile, LockMode};
+use encage_build::resolve::Resolver;
+
+fn package(name: &str, version: &str) -> Package {
+	Package {
+		name: name.to_owned(),
+		version: semver::Version::parse(version).unwrap(),
+	}
+}
+
+#[test]
+fn lockfile_round_trip() {
+	let mut lock = Lockfile::new();
+	lock.lock_package(&package("org.example.base", "1.2.0"), LockMode::Update).unwrap();
+	lock.lock_file("http://example.com/a \"quoted\".tar", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", LockMode::Update).unwrap();
+
+	let mut data = Vec::new();
+	lock.write(&mut data).unwrap();
+	let read = Lockfile::read(&mut &data[..]).expect("read failed");
+
+	assert_eq!(read, lock);
+	assert_eq!(read.package("org.example.base"), Some(&semver::Version::parse("1.2.0").unwrap()));
+	assert_eq!(read.file("http://example.com/a \"quoted\".tar"), Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"));
+}
+
+#[test]
+fn resolve_prefers_locked() {
+	let available = [package("org.example.base", "1.0.0"), package("org.example.base", "1.1.0")];
+	let mut lock = Lockfile::new();
+	lock.lock_package(&available[0], LockMode::Update).unwrap();
+
+	let mut resolver = Resolver::new(&available);
+	resolver.require(PackageQuery::parse("org.example.base@^1").unwrap(), None);
+	assert_eq!(resolver.resolve(&mut lock, LockMode::Locked).unwrap(), [available[0].clone()]);
+
+	// Without a pin, the highest compatible version wins.
+	let mut lock = Lockfile::new();
+	assert_eq!(resolver.resolve(&mut lock, LockMode::Update).unwrap(), [available[1].clone()]);
+	assert_eq!(lock.package("org.example.base"), Some(&available[1].version));
+}
+
+#[test]
+fn resolve_locked_mismatch() {
+	let available = [package("org.example.base", "1.0.0"), package("org.example.base", "1.1.0")];
+	let mut lock = Lockfile::new();
+	lock.lock_package(&available[0], LockMode::Update).unwrap();
+
+	let mut resolver = Resolver::new(&available);
+	resolver.require(PackageQuery::parse("org.example.base@^1.1").unwrap(), None);
+	let err = resolver.resolve(&mut lock.clone(), LockMode::Locked).err().expect("locked version was changed").to_string();
+	assert!(err.contains("the lockfile is locked"), "{}", err);
+
+	// Updating moves the pin instead.
+	resolver.resolve(&mut lock, LockMode::Update).unwrap();
+	assert_eq!(lock.package("org.example.base"), Some(&available[1].version));
+}
+
+#[test]
+fn lock_file_mismatch() {
+	let mut lock = Lockfile::new();
+	lock.lock_file("http://example.com/a.tar", "aaaa", LockMode::Update).unwrap();
+
+	assert!(lock.lock_file("http://example.com/a.tar", "aaaa", LockMode::Locked).is_ok());
+	let err = lock.lock_file("http://example.com/a.tar", "bbbb", LockMode::Update).err().expect("hash change was accepted").to_string();
+	assert!(err.contains("expected hash aaaa, fetched bbbb"), "{}", err);
+	assert!(lock.lock_file("http://example.com/b.tar", "cccc", LockMode::Locked).is_err());
+}
@@ -10,3 +10,170 @@ fn parse() {
 
 	config.expect("parse failed");
 }
+
+#[test]
+fn parse_unknown_key() {
+	let data = b"
+[package]
+name = \"encage.unknown-key\"
+version = \"0.0.1\"
+plugins = [\"base\"]
+
+[[image]]
+name = \".busybox\"
+build_commands = \"true\"
+";
+
+	let mut plugins = encage_build::plugins::Registry::new();
+	plugins.register_builtins();
+	let err = encage_build::parse::parse(&mut &data[..], &plugins).err().expect("parse succeeded");
+
+	let err = err.to_string();
+	assert!(err.contains("encage.unknown-key.busybox"));
+	assert!(err.contains("build-commands"));
+}
+
+#[test]
+fn parse_tabs() {
+	let data = b"
+[package]
+name = \"encage.tabs\"
+version = \"0.0.1\"
+plugins = [\"base\"]
+
+[[image]]
+name = \".busybox\"
+commands = \"\"\"
+cat > Makefile <<EOF
+all:
+\ttrue
+EOF
+\"\"\"
+
+[[image]]
+name = \".dedent\"
+dedent = true
+commands = \"\"\"
+\tif true; then
+\t\ttrue
+\tfi
+\"\"\"
+";
+
+	let mut plugins = encage_build::plugins::Registry::new();
+	plugins.register_builtins();
+	let recipe = encage_build::parse::parse(&mut &data[..], &plugins).expect("parse failed");
+
+	let commands: Vec<_> = recipe.images.iter()
+		.map(|i| i.plugin_data.get::<encage_build::plugins::commands::CommandsPlugin>().expect("no commands").commands[0].0.args()[2].to_owned())
+		.collect();
+	assert!(commands[0].contains("all:\n\ttrue\n"));
+	assert!(commands[1].contains("if true; then\n\ttrue\nfi\n"));
+}
+
+#[test]
+fn parse_error_position() {
+	let data = b"[package]\nname = \"encage.error\"\n\tversion = \"0.0.1\" x\n";
+
+	let plugins = encage_build::plugins::Registry::new();
+	let err = encage_build::parse::parse(&mut &data[..], &plugins).err().expect("parse succeeded");
+
+	assert!(err.to_string().starts_with("3:"));
+}
+
+#[test]
+fn parse_limits() {
+	let data = b"\
+[package]
+name = \"encage.limits\"
+version = \"0.0.1\"
+plugins = [\"base\"]
+
+[[image]]
+name = \".compile\"
+limits = { memory = \"2G\", cpus = 1.5, timeout = \"30m\" }
+commands = [
+	{ command = \"make\" },
+	{ command = [\"make\", \"check\"], limits = { memory = 1024, pids = 64 } },
+]
+";
+
+	let mut plugins = encage_build::plugins::Registry::new();
+	plugins.register_builtins();
+	let recipe = encage_build::parse::parse(&mut &data[..], &plugins).expect("parse failed");
+
+	let commands = recipe.images[0].plugin_data.get::<encage_build::plugins::commands::CommandsPlugin>().expect("no commands");
+	assert_eq!(commands.limits.memory, Some(2 << 30));
+	assert_eq!(commands.limits.timeout, Some(30 * 60));
+	assert!(commands.commands[0].1.is_empty());
+
+	let check = commands.commands[1].1.or(&commands.limits);
+	assert_eq!(check.args(), ["--memory", "1024", "--cpus", "1.5", "--pids", "64", "--timeout", "1800"]);
+
+	let data = b"[package]\nname = \"encage.limits\"\nversion = \"0.0.1\"\nplugins = [\"base\"]\n[[image]]\nname = \".a\"\nlimits = { memory = \"lots\" }\n";
+	assert!(encage_build::parse::parse(&mut &data[..], &plugins).is_err());
+}
+
+#[test]
+fn parse_network() {
+	use encage_build::command::Network;
+	use encage_build::console::{DefaultConsole, LogLevel};
+	use encage_build::context::Context;
+
+	let data = b"\
+[package]
+name = \"encage.network\"
+version = \"0.0.1\"
+plugins = [\"base\"]
+
+[[image]]
+name = \".fetch\"
+network = \"loopback\"
+commands = [
+	{ command = \"curl -O http://example.com/a.tar\", network = \"host\" },
+	\"make\",
+]
+";
+
+	let mut plugins = encage_build::plugins::Registry::new();
+	plugins.register_builtins();
+	let recipe = encage_build::parse::parse(&mut &data[..], &plugins).expect("parse failed");
+
+	let commands = recipe.images[0].plugin_data.get::<encage_build::plugins::commands::CommandsPlugin>().expect("no commands");
+	assert_eq!(commands.network, Network::Loopback);
+	assert_eq!(commands.commands[0].2, Some(Network::Host));
+	assert_eq!(commands.commands[1].2, None);
+
+	let data = b"[package]\nname = \"encage.network\"\nversion = \"0.0.1\"\nplugins = [\"base\"]\n[[image]]\nname = \".a\"\ncommands = [ { command = \"true\", network = \"internet\" } ]\n";
+	let err = encage_build::parse::parse(&mut &data[..], &plugins).err().expect("parse succeeded");
+	assert!(err.to_string().contains("unknown network `internet`"), "{}", err);
+
+	// Host commands can't opt into a network of their own either.
+	let data = b"[package]\nname = \"encage.network\"\nversion = \"0.0.1\"\nplugins = [\"base\"]\n[[image]]\nname = \".a\"\ntype = \"host\"\ncommands = [ { command = \"true\", network = \"loopback\" } ]\n";
+	let recipe = encage_build::parse::parse(&mut &data[..], &plugins).expect("parse failed");
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	context.load_recipe(&plugins, recipe).expect("load failed");
+	let err = context.resolve_dependencies(&plugins).err().expect("host command got its own network");
+	assert!(err.to_string().contains("host images always use the host network"), "{}", err);
+}
+
+#[test]
+fn devices() {
+	use encage_build::console::{DefaultConsole, LogLevel};
+	use encage_build::context::Context;
+
+	let mut plugins = encage_build::plugins::Registry::new();
+	plugins.register_builtins();
+
+	let data = b"[package]\nname = \"encage.devices\"\nversion = \"0.0.1\"\nplugins = [\"base\"]\n[[image]]\nname = \".a\"\ndevices = [\"/dev/fuse\"]\ncommands = \"true\"\n";
+	let recipe = encage_build::parse::parse(&mut &data[..], &plugins).expect("parse failed");
+	let commands = recipe.images[0].plugin_data.get::<encage_build::plugins::commands::CommandsPlugin>().expect("no commands");
+	assert_eq!(commands.devices, ["/dev/fuse"]);
+
+	let data = b"[package]\nname = \"encage.devices\"\nversion = \"0.0.1\"\nplugins = [\"base\"]\n[[image]]\nname = \".a\"\ntype = \"host\"\ndevices = [\"/dev/fuse\"]\ncommands = \"true\"\n";
+	let recipe = encage_build::parse::parse(&mut &data[..], &plugins).expect("parse failed");
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	context.load_recipe(&plugins, recipe).expect("load failed");
+	let err = context.resolve_dependencies(&plugins).err().expect("host command got a device");
+	assert!(err.to_string().contains("host commands always see every host device"), "{}", err);
+}
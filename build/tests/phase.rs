@@ -0,0 +1,47 @@
+extern crate encage_build;
+
+use encage_build::phase::{self, Phase, PhaseDecl, PhaseRegistry};
+
+fn names(phases: &[Phase]) -> Vec<&str> {
+	phases.iter().map(|p| p.name()).collect()
+}
+
+#[test]
+fn order_builtins() {
+	let mut phases = PhaseRegistry::new();
+	phases.register_builtins();
+
+	assert_eq!(names(&phases.order().unwrap()), ["fetch", "stage", "build", "post", "image", "export"]);
+}
+
+#[test]
+fn order_constraints() {
+	let mut phases = PhaseRegistry::new();
+	phases.declare(PhaseDecl::new(Phase::new("lint")));
+	phases.register_builtins();
+	phases.declare(PhaseDecl::new(Phase::new("check")).after(phase::PHASE_BUILD).before(phase::PHASE_POST));
+	// Constraints on an existing phase are merged into it.
+	phases.declare(PhaseDecl::new(Phase::new("lint")).after(phase::PHASE_STAGE));
+
+	assert_eq!(names(&phases.order().unwrap()), ["fetch", "stage", "lint", "build", "check", "post", "image", "export"]);
+}
+
+#[test]
+fn order_cycle() {
+	let mut phases = PhaseRegistry::new();
+	phases.declare(PhaseDecl::new(phase::PHASE_FETCH));
+	phases.declare(PhaseDecl::new(Phase::new("a")).after(phase::PHASE_FETCH).after(Phase::new("b")));
+	phases.declare(PhaseDecl::new(Phase::new("b")).after(Phase::new("a")));
+
+	let err = phases.order().err().expect("cycle was ordered").to_string();
+	assert!(err.contains("cyclic ordering constraints: a, b"), "{}", err);
+}
+
+#[test]
+fn order_unknown() {
+	let mut phases = PhaseRegistry::new();
+	phases.declare(PhaseDecl::new(Phase::new("a")).before(Phase::new("missing")));
+
+	let err = phases.order().err().expect("unknown phase was ordered").to_string();
+	assert!(err.contains("unknown phase `missing`"), "{}", err);
+}
@@ -0,0 +1,95 @@
+extern crate encage_build;
+extern crate semver;
+
+use std::fmt;
+use encage_build::build::{BuildItem, BuildClass, BuildContext, BuildDependencyContext, NullBuildItem};
+use encage_build::config::Package;
+use encage_build::console::{DefaultConsole, LogLevel};
+use encage_build::context::Context;
+use encage_build::phase;
+use encage_build::plugins::Registry;
+
+#[derive(Debug)]
+struct LayerItem(Package);
+
+impl BuildItem for LayerItem {
+	fn class(&self) -> Option<BuildClass> { Some(phase::PHASE_FETCH) }
+
+	fn register_dependencies(&self, context: &mut BuildDependencyContext) -> encage_build::Result<()> {
+		context.depends_on(&self.0, &phase::PHASE_IMAGE);
+		Ok(())
+	}
+
+	fn build(&self, _context: &mut BuildContext) -> encage_build::Result<()> { Ok(()) }
+}
+
+impl fmt::Display for LayerItem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "depends {}", self.0.name)
+	}
+}
+
+/// Resolves a synthetic chain of images, each layered on the previous one,
+/// to catch dependency resolution becoming quadratic in the number of items.
+#[test]
+fn resolve_large_package_set() {
+	const IMAGES: usize = 5000;
+
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	let version = semver::Version::parse("1.0.0").unwrap();
+	let packages: Vec<Package> = (0..IMAGES).map(|i| Package {
+		name: format!("org.example.monorepo.image{}", i),
+		version: version.clone(),
+	}).collect();
+
+	for (i, package) in packages.iter().enumerate() {
+		if i > 0 {
+			context.register_build_item(package, LayerItem(packages[i - 1].clone()));
+		}
+		context.register_build_item(package, NullBuildItem::new("stage", Some(phase::PHASE_STAGE)));
+		context.register_build_item(package, NullBuildItem::new("image", Some(phase::PHASE_IMAGE)));
+	}
+
+	context.resolve_dependencies(&Registry::new()).expect("resolve failed");
+
+	let last = context.image_dependency(packages.last().unwrap()).unwrap();
+	assert_eq!(context.dependency_graph.order(last).unwrap().len(), context.build_items.len());
+}
+
+#[test]
+fn layer_chain() {
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	let version = semver::Version::parse("1.0.0").unwrap();
+	let package = |name: &str| Package {
+		name: name.to_owned(),
+		version: version.clone(),
+	};
+
+	context.register_layers(&package("app"), vec![package("devel"), package("base")]);
+	context.register_layers(&package("devel"), vec![package("base")]);
+	context.register_layers(&package("base"), vec![package("busybox")]);
+
+	assert_eq!(context.layers(&package("app")), vec![package("devel"), package("base"), package("busybox")]);
+	assert!(context.layers(&package("busybox")).is_empty());
+
+	// Declared before the layer built on it, base still ends up below devel.
+	context.register_layers(&package("tools"), vec![package("base"), package("devel")]);
+	assert_eq!(context.layers(&package("tools")), vec![package("devel"), package("base"), package("busybox")]);
+}
+
+#[test]
+fn layer_chain_diamond() {
+	let mut context = Context::new(DefaultConsole::new(LogLevel::Error));
+	let version = semver::Version::parse("1.0.0").unwrap();
+	let package = |name: &str| Package {
+		name: name.to_owned(),
+		version: version.clone(),
+	};
+
+	context.register_layers(&package("app"), vec![package("left"), package("right")]);
+	context.register_layers(&package("left"), vec![package("base")]);
+	context.register_layers(&package("right"), vec![package("base")]);
+
+	assert_eq!(context.layers(&package("app")), vec![package("left"), package("right"), package("base")]);
+	assert_eq!(context.layers(&package("right")), vec![package("base")]);
+}
@@ -0,0 +1,64 @@
+extern crate encage_build;
+
+use std::collections::HashSet;
+use encage_build::dependencies::DependencyGraph;
+use encage_build::schedule::Schedule;
+
+#[test]
+fn schedule_diamond() {
+	let mut graph = DependencyGraph::new();
+	let (base, left, right, top) = (graph.register(), graph.register(), graph.register(), graph.register());
+	graph.link(left, base);
+	graph.link(right, base);
+	graph.link(top, left);
+	graph.link(top, right);
+
+	let order = graph.order(top).unwrap();
+	let mut schedule = Schedule::new(&graph, &order, &HashSet::new());
+	assert_eq!(schedule.next_ready(), Some(base));
+	assert_eq!(schedule.next_ready(), None);
+
+	schedule.complete(base);
+	let ready = [schedule.next_ready().unwrap(), schedule.next_ready().unwrap()];
+	assert!(ready.contains(&left) && ready.contains(&right));
+	assert_eq!(schedule.next_ready(), None);
+
+	schedule.complete(left);
+	assert_eq!(schedule.next_ready(), None);
+	schedule.complete(right);
+	assert_eq!(schedule.next_ready(), Some(top));
+	schedule.complete(top);
+	assert_eq!(schedule.blocked(), 0);
+}
+
+#[test]
+fn schedule_completed() {
+	let mut graph = DependencyGraph::new();
+	let (base, top) = (graph.register(), graph.register());
+	graph.link(top, base);
+
+	let order = graph.order(top).unwrap();
+	let completed = Some(base).into_iter().collect();
+	let mut schedule = Schedule::new(&graph, &order, &completed);
+	assert_eq!(schedule.next_ready(), Some(top));
+	assert_eq!(schedule.next_ready(), None);
+}
+
+#[test]
+fn schedule_blocked() {
+	let mut graph = DependencyGraph::new();
+	let (a, b, c, top) = (graph.register(), graph.register(), graph.register(), graph.register());
+	graph.link(c, a);
+	graph.link(top, b);
+	graph.link(top, c);
+
+	let order = graph.order(top).unwrap();
+	let mut schedule = Schedule::new(&graph, &order, &HashSet::new());
+	assert_eq!(schedule.blocked(), 4);
+
+	// `a` fails, so it never completes and nothing else is started: `b`
+	// was ready but never taken, while `c` and `top` are still waiting.
+	let first = schedule.next_ready().unwrap();
+	assert!(first == a || first == b);
+	assert_eq!(schedule.blocked(), 3);
+}
@@ -0,0 +1,32 @@
+extern crate encage_build;
+extern crate semver;
+
+use std::path::Path;
+use encage_build::config::Package;
+use encage_build::fs::{Filesystem, MemoryFilesystem};
+use encage_build::work::Workspace;
+
+#[test]
+fn workspace_layout() {
+	let workspace = Workspace::with_filesystem("/build", MemoryFilesystem::new());
+	let package = Package {
+		name: "encage.hello-demo.busybox".into(),
+		version: semver::Version::parse("0.0.1").unwrap(),
+	};
+
+	workspace.prepare(&package).expect("prepare failed");
+	let fs = workspace.filesystem();
+	for dir in &["stage", "res", "stamp", "workdir"] {
+		assert!(fs.exists(&Path::new("/build/encage.hello-demo.busybox-0.0.1").join(dir)));
+	}
+
+	assert!(!workspace.is_stamped(&package, "commands"));
+	workspace.stamp(&package, "commands").expect("stamp failed");
+	assert!(workspace.is_stamped(&package, "commands"));
+
+	let stage = workspace.staging_filesystem(&package);
+	stage.create_dir_all(Path::new("/sbin")).unwrap();
+	stage.write(Path::new("/sbin/busybox"), b"busybox").unwrap();
+	assert_eq!(fs.read(Path::new("/build/encage.hello-demo.busybox-0.0.1/stage/sbin/busybox")).unwrap(), b"busybox");
+	assert_eq!(stage.read_dir(Path::new("/")).unwrap(), vec![Path::new("/sbin").to_path_buf()]);
+}
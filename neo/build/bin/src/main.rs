@@ -4,11 +4,14 @@ extern crate encage_build as build;
 extern crate encage_build_schema as schema;
 extern crate encage_build_dag as dag;
 
-use clap::{App, AppSettings};
-use std::fs::File;
+use clap::{App, AppSettings, ArgMatches};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
 
 fn main() {
-	let app = App::new("encage-build");
+	let app = App::new("encage-build").setting(AppSettings::SubcommandsNegateReqs);
 	let app = clap_app! { @app (app)
 		(author: "arcnmx")
 		(about: "Encage build")
@@ -16,16 +19,143 @@ fn main() {
 		(@arg FORMAT: --format -f +takes_value "Output format: makefile, ninja")
 		(@arg OUTPUT: --output -o +takes_value "The output file")
 		(@arg INPUT: +required "The build recipe")
+		(@subcommand run =>
+			(about: "Runs a command in the built image, sh by default")
+			(@arg NETWORK: --network +takes_value "Network: none, loopback, host")
+			(@arg INPUT: +required "The build recipe")
+			(@arg COMMAND: ... "The command to run, after --")
+		)
+		(@subcommand shell =>
+			(about: "Opens a shell where a build step runs, on top of what the steps before it built")
+			(@arg AT: --at +takes_value +required "The step, counting commands from 1; only steps run in the image itself, not host or ocf ones")
+			(@arg INPUT: +required "The build recipe")
+		)
 	};
 
 	let matches = app.get_matches();
 
+	match matches.subcommand() {
+		("run", Some(matches)) => run(matches),
+		("shell", Some(matches)) => shell(matches),
+		_ => generate(&matches),
+	}
+}
+
+fn load(input: &str) -> schema::ImageRecipe {
+	let input = File::open(input).expect("failed to open input file");
+	schema::load(input).expect("failed to read input file")
+}
+
+fn fail<S: AsRef<str>>(message: S) -> ! {
+	let _ = writeln!(io::stderr(), "encage-build: {}", message.as_ref());
+	process::exit(1)
+}
+
+/// Runs `args`, exiting with its exit code.
+fn spawn(image: &schema::Image, args: Vec<String>) -> i32 {
+	let status = process::Command::new(&args[0]).args(&args[1..])
+		// Host steps find the image here.
+		.env("ENCAGE_TARGET", &image.dest)
+		.status();
+
+	match status {
+		Ok(status) => status.code().unwrap_or(1),
+		Err(e) => fail(format!("failed to run {}: {}", args[0], e)),
+	}
+}
+
+/// Runs the way image commands do, unless told otherwise.
+fn image_exec(network: schema::Network) -> schema::CommandExec {
+	schema::CommandExec {
+		kind: schema::CommandExecType::Image,
+		cwd: None,
+		network: network,
+		limits: schema::Limits::default(),
+		commands: Vec::new(),
+	}
+}
+
+fn run(matches: &ArgMatches) {
+	let schema = load(matches.value_of("INPUT").unwrap());
+	let network = match matches.value_of("NETWORK") {
+		Some(network) => schema::Network::from_str(network).unwrap_or_else(|| fail(format!("unknown network {}", network))),
+		None => schema::Network::None,
+	};
+	let command: Vec<&str> = matches.values_of("COMMAND").map(|v| v.into_iter().collect()).unwrap_or_else(|| vec!["sh"]);
+
+	let args = build::exec_args(&schema.image, &image_exec(network), &command, &build::RunOptions {
+		tty: true,
+		scratch: None,
+	});
+	process::exit(spawn(&schema.image, args))
+}
+
+/// Opens a shell the way a step runs, with its mounts, environment and
+/// limits, in the image as the steps before it left it. Changes made from the
+/// shell go to a scratch layer that is thrown away afterwards, though
+/// anything a failed step wrote before failing is still there. Steps from
+/// `--at` on must not have been built yet, and host and ocf steps are
+/// refused, as they'd write to the image itself rather than the scratch layer.
+fn shell(matches: &ArgMatches) {
+	let schema = load(matches.value_of("INPUT").unwrap());
+	let at = match matches.value_of("AT").unwrap().parse::<usize>() {
+		Ok(at) if at >= 1 && at <= schema.commands.len() => at,
+		_ => fail(format!("--at must be a step between 1 and {}", schema.commands.len())),
+	};
+
+	for (step, command) in schema.commands[..at - 1].iter().enumerate() {
+		let stamp = build::Stamp::new("stamp-", command).to_string();
+		if !Path::new(&stamp).exists() {
+			fail(format!("step {} hasn't been built yet", step + 1))
+		}
+	}
+
+	// Later steps have already changed the image, and there's no snapshot
+	// of it from before they ran.
+	for (step, command) in schema.commands.iter().enumerate().skip(at - 1) {
+		let stamp = build::Stamp::new("stamp-", command).to_string();
+		if Path::new(&stamp).exists() {
+			fail(format!("step {} has already been built, so the image no longer looks the way step {} saw it", step + 1, at))
+		}
+	}
+
+	let exec = match schema.commands[at - 1] {
+		schema::Command::Exec(ref exec) => exec.clone(),
+		schema::Command::Copy(..) => image_exec(schema::Network::None),
+	};
+	match exec.kind {
+		schema::CommandExecType::Image => (),
+		_ => fail(format!("step {} runs outside the image, so a shell there couldn't keep its changes out of it", at)),
+	}
+
+	let scratch = format!("{}.shell", schema.image.dest.trim_right_matches('/'));
+	let work = format!("{}.work", scratch);
+	fs::create_dir_all(&scratch).unwrap_or_else(|e| fail(format!("failed to create {}: {}", scratch, e)));
+
+	let args = build::exec_args(&schema.image, &exec, &["sh"], &build::RunOptions {
+		tty: true,
+		scratch: Some(&scratch),
+	});
+	let code = spawn(&schema.image, args);
+
+	for dir in &[&scratch, &work] {
+		if let Err(e) = fs::remove_dir_all(dir) {
+			if e.kind() != io::ErrorKind::NotFound {
+				let _ = writeln!(io::stderr(), "encage-build: failed to remove {}: {}", dir, e);
+			}
+		}
+	}
+
+	process::exit(code)
+}
+
+/// Writes the build of the recipe as a makefile.
+fn generate(matches: &ArgMatches) {
 	let input = matches.value_of("INPUT").unwrap();
 	let engine = matches.value_of("ENGINE").unwrap_or("encage");
 	let output = matches.value_of("OUTPUT").and_then(|o| matches.value_of("FORMAT").map(|f| (o, f)));
 
-	let input = File::open(input).expect("failed to open input file");
-	let schema = schema::load(input).expect("failed to read input file");
+	let schema = load(input);
 	let mut dag = dag::Dag::new();
 
 	let mut last_stamp = None;
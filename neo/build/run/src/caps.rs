@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::{self, Read};
+use libc;
+
+/// Capabilities commands keep unless more are asked for: enough to install
+/// packages and manage file ownership, but not to administer the system.
+pub const DEFAULT: &[u32] = &[
+	CAP_CHOWN, CAP_DAC_OVERRIDE, CAP_FOWNER, CAP_FSETID, CAP_KILL,
+	CAP_SETGID, CAP_SETUID, CAP_SETPCAP, CAP_NET_BIND_SERVICE,
+	CAP_SYS_CHROOT, CAP_MKNOD, CAP_AUDIT_WRITE, CAP_SETFCAP,
+];
+
+pub const CAP_CHOWN: u32 = 0;
+pub const CAP_DAC_OVERRIDE: u32 = 1;
+pub const CAP_FOWNER: u32 = 3;
+pub const CAP_FSETID: u32 = 4;
+pub const CAP_KILL: u32 = 5;
+pub const CAP_SETGID: u32 = 6;
+pub const CAP_SETUID: u32 = 7;
+pub const CAP_SETPCAP: u32 = 8;
+pub const CAP_NET_BIND_SERVICE: u32 = 10;
+pub const CAP_SYS_CHROOT: u32 = 18;
+pub const CAP_MKNOD: u32 = 27;
+pub const CAP_AUDIT_WRITE: u32 = 29;
+pub const CAP_SETFCAP: u32 = 31;
+
+/// Every capability by number, as named in capabilities(7) without the `CAP_` prefix.
+const NAMES: &[&str] = &[
+	"CHOWN", "DAC_OVERRIDE", "DAC_READ_SEARCH", "FOWNER", "FSETID", "KILL",
+	"SETGID", "SETUID", "SETPCAP", "LINUX_IMMUTABLE", "NET_BIND_SERVICE",
+	"NET_BROADCAST", "NET_ADMIN", "NET_RAW", "IPC_LOCK", "IPC_OWNER",
+	"SYS_MODULE", "SYS_RAWIO", "SYS_CHROOT", "SYS_PTRACE", "SYS_PACCT",
+	"SYS_ADMIN", "SYS_BOOT", "SYS_NICE", "SYS_RESOURCE", "SYS_TIME",
+	"SYS_TTY_CONFIG", "MKNOD", "LEASE", "AUDIT_WRITE", "AUDIT_CONTROL",
+	"SETFCAP", "MAC_OVERRIDE", "MAC_ADMIN", "SYSLOG", "WAKE_ALARM",
+	"BLOCK_SUSPEND", "AUDIT_READ",
+];
+
+/// Parses a capability name such as `SYS_PTRACE`, `CAP_SYS_PTRACE` or `sys_ptrace`.
+pub fn from_str(s: &str) -> Option<u32> {
+	let s = s.to_uppercase();
+	let name = s.strip_prefix("CAP_").unwrap_or(&s);
+	NAMES.iter().position(|&n| n == name).map(|cap| cap as u32)
+}
+
+const PR_CAPBSET_DROP: libc::c_int = 24;
+const PR_CAP_AMBIENT: libc::c_int = 47;
+const PR_CAP_AMBIENT_CLEAR_ALL: libc::c_ulong = 4;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[repr(C)]
+struct CapHeader {
+	version: u32,
+	pid: libc::c_int,
+}
+
+#[repr(C)]
+struct CapData {
+	effective: u32,
+	permitted: u32,
+	inheritable: u32,
+}
+
+#[cfg(target_arch = "x86_64")]
+const SYS_CAPSET: libc::c_long = 126;
+#[cfg(target_arch = "aarch64")]
+const SYS_CAPSET: libc::c_long = 91;
+#[cfg(target_arch = "x86")]
+const SYS_CAPSET: libc::c_long = 185;
+
+/// Drops every capability not in `keep` from the calling process, including
+/// its bounding set so they can't be regained by executing anything.
+pub fn restrict(keep: &[u32]) -> io::Result<()> {
+	for cap in 0..last_cap() + 1 {
+		if !keep.contains(&cap) && unsafe { libc::prctl(PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0) } < 0 {
+			let err = io::Error::last_os_error();
+			// Capabilities newer than the kernel don't exist to drop.
+			if err.raw_os_error() != Some(libc::EINVAL) {
+				return Err(err)
+			}
+		}
+	}
+
+	// Ambient capabilities only exist since Linux 4.3.
+	if unsafe { libc::prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0) } < 0 {
+		let err = io::Error::last_os_error();
+		if err.raw_os_error() != Some(libc::EINVAL) {
+			return Err(err)
+		}
+	}
+
+	let mut data = [CapData { effective: 0, permitted: 0, inheritable: 0 }, CapData { effective: 0, permitted: 0, inheritable: 0 }];
+	for &cap in keep {
+		let set = &mut data[cap as usize / 32];
+		set.effective |= 1 << (cap % 32);
+		set.permitted |= 1 << (cap % 32);
+		set.inheritable |= 1 << (cap % 32);
+	}
+	let header = CapHeader {
+		version: LINUX_CAPABILITY_VERSION_3,
+		pid: 0,
+	};
+
+	if unsafe { libc::syscall(SYS_CAPSET, &header, data.as_ptr()) } < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(())
+	}
+}
+
+/// The highest capability the kernel knows of.
+fn last_cap() -> u32 {
+	let mut s = String::new();
+	File::open("/proc/sys/kernel/cap_last_cap").and_then(|mut f| f.read_to_string(&mut s)).ok()
+		.and_then(|_| s.trim().parse().ok())
+		.unwrap_or(NAMES.len() as u32 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::from_str;
+
+	#[test]
+	fn names() {
+		assert_eq!(from_str("CHOWN"), Some(0));
+		assert_eq!(from_str("SYS_PTRACE"), Some(19));
+		assert_eq!(from_str("CAP_SYS_PTRACE"), Some(19));
+		assert_eq!(from_str("cap_sys_admin"), Some(21));
+		assert_eq!(from_str("AUDIT_READ"), Some(37));
+		assert_eq!(from_str("CAP_"), None);
+		assert_eq!(from_str("SYS_PTRAC"), None);
+		assert_eq!(from_str(""), None);
+	}
+}
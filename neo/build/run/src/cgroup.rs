@@ -0,0 +1,206 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{cmp, env, thread};
+use std::time::Duration;
+use config::Limits;
+use sys;
+
+/// Where the cgroup v2 hierarchy is expected to be mounted.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// A cgroup enforcing the limits of a single command.
+///
+/// It is created below the cgroup named by `$ENCAGE_CGROUP`, or else the one
+/// encage-run itself runs in. That parent must be delegated to the caller.
+/// A cgroup can't contain processes of its own once controllers are enabled
+/// for its children. Any processes in an explicit `$ENCAGE_CGROUP` are first
+/// moved into its `encage-leaf` child, but those of encage-run's own cgroup
+/// are left alone, as they likely belong to whatever started it.
+pub struct Cgroup {
+	path: PathBuf,
+}
+
+impl Cgroup {
+	/// Creates a cgroup for `limits`, or returns `None` if there aren't any it needs to enforce.
+	pub fn create(limits: &Limits) -> io::Result<Option<Self>> {
+		let mut controllers = Vec::new();
+		if limits.memory.is_some() {
+			controllers.push("memory");
+		}
+		if limits.cpus.is_some() {
+			controllers.push("cpu");
+		}
+		if limits.pids.is_some() {
+			controllers.push("pids");
+		}
+		if controllers.is_empty() {
+			return Ok(None)
+		}
+
+		let root = Path::new(CGROUP_ROOT);
+		if fs::metadata(root.join("cgroup.controllers")).is_err() {
+			return Err(io::Error::new(io::ErrorKind::NotFound, format!("resource limits require cgroup v2 mounted at {}", CGROUP_ROOT)))
+		}
+
+		let (parent, relocate) = match env::var("ENCAGE_CGROUP") {
+			Ok(parent) => (parent, true),
+			Err(..) => (current()?, false),
+		};
+		let parent = root.join(parent.trim_start_matches('/'));
+
+		enable_controllers(&parent, &controllers, relocate).map_err(|e|
+			io::Error::new(e.kind(), format!("{} (set ENCAGE_CGROUP to a cgroup delegated to this user)", e))
+		)?;
+
+		let path = parent.join(format!("encage-run-{}", sys::getpid()));
+		fs::create_dir(&path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+		let cgroup = Cgroup {
+			path,
+		};
+
+		if let Some(memory) = limits.memory {
+			write(&cgroup.path.join("memory.max"), &memory.to_string())?;
+			// Swapping would only hide the limit, but swap accounting may be disabled.
+			let _ = write(&cgroup.path.join("memory.swap.max"), "0");
+		}
+		if let Some(cpus) = limits.cpus {
+			let period = 100000;
+			// The kernel rejects quotas below 1ms.
+			let quota = cmp::max((cpus * period as f64) as u64, 1000);
+			write(&cgroup.path.join("cpu.max"), &format!("{} {}", quota, period))?;
+		}
+		if let Some(pids) = limits.pids {
+			write(&cgroup.path.join("pids.max"), &pids.to_string())?;
+		}
+
+		Ok(Some(cgroup))
+	}
+
+	/// Moves the calling process into the cgroup.
+	pub fn join(&self) -> io::Result<()> {
+		write(&self.path.join("cgroup.procs"), "0")
+	}
+
+	/// Describes the limit that was hit, if any.
+	pub fn exceeded(&self, limits: &Limits) -> Option<String> {
+		if let Some(memory) = limits.memory {
+			if event_count(&self.path.join("memory.events"), "oom_kill") > 0 {
+				return Some(format!("memory limit of {} exceeded", format_size(memory)))
+			}
+		}
+		if let Some(pids) = limits.pids {
+			if event_count(&self.path.join("pids.events"), "max") > 0 {
+				return Some(format!("process limit of {} reached", pids))
+			}
+		}
+
+		None
+	}
+
+	/// Removes the cgroup once every process in it has exited.
+	pub fn remove(self) -> io::Result<()> {
+		let mut retries = 50;
+		loop {
+			match fs::remove_dir(&self.path) {
+				// The kernel may take a moment to empty the cgroup after the last process exits.
+				Err(ref e) if e.raw_os_error() == Some(::libc::EBUSY) && retries > 0 => {
+					retries -= 1;
+					thread::sleep(Duration::from_millis(10));
+				},
+				res => return res,
+			}
+		}
+	}
+}
+
+/// Enables `controllers` for the children of `parent`. If `relocate` is set,
+/// every process in `parent` is moved into a leaf child if it has any,
+/// otherwise their presence is an error.
+fn enable_controllers(parent: &Path, controllers: &[&str], relocate: bool) -> io::Result<()> {
+	let subtree_control = parent.join("cgroup.subtree_control");
+	let enabled = read(&subtree_control)?;
+	let missing: Vec<_> = controllers.iter().filter(|&&controller| !enabled.split_whitespace().any(|c| c == controller)).map(|c| format!("+{}", c)).collect();
+	if missing.is_empty() {
+		return Ok(())
+	}
+
+	// Processes forked while moving the others may still land in the parent.
+	let mut retries = 3;
+	loop {
+		match write(&subtree_control, &missing.join(" ")) {
+			Err(ref e) if e.raw_os_error() == Some(::libc::EBUSY) && relocate && retries > 0 => retries -= 1,
+			res => return res,
+		}
+
+		let leaf = parent.join("encage-leaf");
+		match fs::create_dir(&leaf) {
+			Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+			res => res.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", leaf.display(), e)))?,
+		}
+		for pid in read(&parent.join("cgroup.procs"))?.lines() {
+			match write(&leaf.join("cgroup.procs"), pid) {
+				// The process exited in the meantime.
+				Err(ref e) if e.raw_os_error() == Some(::libc::ESRCH) => (),
+				res => res?,
+			}
+		}
+	}
+}
+
+/// The cgroup v2 path of the calling process, relative to the hierarchy root.
+fn current() -> io::Result<String> {
+	let cgroups = read(Path::new("/proc/self/cgroup"))?;
+	cgroups.lines().filter_map(|line| line.strip_prefix("0::")).map(|path| path.to_owned()).next()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not running in a cgroup v2 hierarchy"))
+}
+
+fn event_count(path: &Path, event: &str) -> u64 {
+	read(path).ok().and_then(|events| events.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			match (fields.next(), fields.next()) {
+				(Some(name), Some(count)) if name == event => count.parse().ok(),
+				_ => None,
+			}
+		}).next()
+	).unwrap_or(0)
+}
+
+fn format_size(bytes: u64) -> String {
+	for &(suffix, size) in &[("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)] {
+		if bytes >= size && bytes.is_multiple_of(size) {
+			return format!("{}{}", bytes / size, suffix)
+		}
+	}
+
+	bytes.to_string()
+}
+
+fn read(path: &Path) -> io::Result<String> {
+	let mut s = String::new();
+	File::open(path).and_then(|mut f| f.read_to_string(&mut s)).map_err(|e|
+		io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+	)?;
+	Ok(s)
+}
+
+fn write(path: &Path, data: &str) -> io::Result<()> {
+	OpenOptions::new().write(true).open(path).and_then(|mut f| f.write_all(data.as_bytes()))
+		.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::format_size;
+
+	#[test]
+	fn sizes() {
+		assert_eq!(format_size(0), "0");
+		assert_eq!(format_size(1000), "1000");
+		assert_eq!(format_size(4096), "4K");
+		assert_eq!(format_size(3 << 20), "3M");
+		assert_eq!(format_size((1 << 30) + (1 << 10)), "1048577K");
+		assert_eq!(format_size(2 << 30), "2G");
+	}
+}
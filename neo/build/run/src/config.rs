@@ -0,0 +1,255 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::{env, io};
+use user::IdMapping;
+use net::Network;
+
+/// How the container root relates to the image being built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+	/// Run inside the image itself.
+	Exec,
+	/// Run inside another container, with the image mounted as the target.
+	Ocf,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mount {
+	pub src: PathBuf,
+	pub dest: PathBuf,
+}
+
+impl Mount {
+	/// Parses `src:dest`.
+	pub fn parse<S: AsRef<OsStr>>(s: S) -> io::Result<Self> {
+		let s = s.as_ref().as_bytes();
+		match s.iter().position(|&c| c == b':') {
+			Some(i) if i > 0 && i + 1 < s.len() => Ok(Mount {
+				src: PathBuf::from(OsStr::from_bytes(&s[..i])),
+				dest: PathBuf::from(OsStr::from_bytes(&s[i + 1..])),
+			}),
+			_ => Err(invalid(format!("invalid mount `{}`, expected src:dest", String::from_utf8_lossy(s)))),
+		}
+	}
+}
+
+/// How lower layers are combined with the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerMode {
+	/// Use overlayfs when the kernel allows it, otherwise copy.
+	Auto,
+	Overlay,
+	Copy,
+}
+
+impl LayerMode {
+	pub fn parse(s: &str) -> Option<Self> {
+		Some(match s {
+			"auto" => LayerMode::Auto,
+			"overlay" => LayerMode::Overlay,
+			"copy" => LayerMode::Copy,
+			_ => return None,
+		})
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Overlay {
+	/// Lower layers, topmost first.
+	pub lower: Vec<PathBuf>,
+	/// Overlayfs scratch space, on the same filesystem as the image.
+	pub work: PathBuf,
+	pub mode: LayerMode,
+}
+
+/// Resources the command may use, each unlimited when `None`.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+	/// Memory in bytes, without swap.
+	pub memory: Option<u64>,
+	/// Cpu time as a number of cpus, such as `1.5`.
+	pub cpus: Option<f64>,
+	/// Number of processes and threads.
+	pub pids: Option<u64>,
+	/// Wall-clock time in seconds.
+	pub timeout: Option<u64>,
+}
+
+impl Limits {
+	/// Parses a size in bytes with an optional `K`, `M` or `G` suffix.
+	pub fn parse_size(s: &str) -> Option<u64> {
+		let (digits, scale) = match s.chars().last() {
+			Some('K') | Some('k') => (&s[..s.len() - 1], 1 << 10),
+			Some('M') | Some('m') => (&s[..s.len() - 1], 1 << 20),
+			Some('G') | Some('g') => (&s[..s.len() - 1], 1 << 30),
+			_ => (s, 1),
+		};
+		digits.parse::<u64>().ok().and_then(|n| n.checked_mul(scale))
+	}
+
+	/// Parses a duration in seconds with an optional `s`, `m` or `h` suffix.
+	pub fn parse_duration(s: &str) -> Option<u64> {
+		let (digits, scale) = match s.chars().last() {
+			Some('s') => (&s[..s.len() - 1], 1),
+			Some('m') => (&s[..s.len() - 1], 60),
+			Some('h') => (&s[..s.len() - 1], 60 * 60),
+			_ => (s, 1),
+		};
+		digits.parse::<u64>().ok().and_then(|n| n.checked_mul(scale)).and_then(|n| if n > 0 { Some(n) } else { None })
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+	pub mode: Mode,
+	pub root: PathBuf,
+	pub args: Vec<OsString>,
+	pub target: Option<PathBuf>,
+	pub cwd: Option<PathBuf>,
+	pub mounts: Vec<Mount>,
+	pub overlay: Option<Overlay>,
+	pub env: Vec<(OsString, OsString)>,
+	pub ids: IdMapping,
+	/// Host devices to expose in addition to the defaults.
+	pub devices: Vec<PathBuf>,
+	pub network: Network,
+	/// Set in the container's UTS namespace and its generated `/etc/hosts`.
+	pub hostname: String,
+	pub limits: Limits,
+	/// Capabilities kept in addition to `caps::DEFAULT`.
+	pub capabilities: Vec<u32>,
+	/// Keeps every capability and skips the seccomp filter, for bootstrap
+	/// images that mount filesystems or otherwise administer the container.
+	pub privileged: bool,
+	/// Runs the command on a pty relayed to the terminal encage-run runs in, if any.
+	pub tty: bool,
+}
+
+pub const DEFAULT_PATH: &str = "/bin:/sbin:/usr/bin:/usr/sbin";
+pub const TARGET_PATH: &str = "/mnt/target";
+/// Keeps the host's hostname from leaking into builds.
+pub const DEFAULT_HOSTNAME: &str = "encage";
+
+impl Config {
+	pub fn new(mode: Mode, root: PathBuf, args: Vec<OsString>) -> Self {
+		Config {
+			mode,
+			root,
+			args,
+			target: None,
+			cwd: None,
+			mounts: Vec::new(),
+			overlay: None,
+			env: Vec::new(),
+			ids: IdMapping::detect(),
+			devices: Vec::new(),
+			network: Network::default(),
+			hostname: DEFAULT_HOSTNAME.into(),
+			limits: Limits::default(),
+			capabilities: Vec::new(),
+			privileged: false,
+			tty: false,
+		}
+	}
+
+	/// Applies the `ENCAGE_MOUNT`, `ENCAGE_OVERLAY` and `ENCAGE_OVERLAY_WORK`
+	/// variables understood by the original shell runtime.
+	pub fn apply_env(&mut self) -> io::Result<()> {
+		if let Some(mounts) = env::var_os("ENCAGE_MOUNT") {
+			for mount in mounts.as_bytes().split(|&c| c == b';').filter(|m| !m.is_empty()) {
+				self.mounts.push(Mount::parse(OsStr::from_bytes(mount))?);
+			}
+		}
+
+		if let Some(lower) = env::var_os("ENCAGE_OVERLAY") {
+			let work = env::var_os("ENCAGE_OVERLAY_WORK").ok_or_else(|| invalid("ENCAGE_OVERLAY requires ENCAGE_OVERLAY_WORK"))?;
+			self.overlay = Some(Overlay {
+				lower: env::split_paths(&lower).collect(),
+				work: PathBuf::from(work),
+				mode: LayerMode::Auto,
+			});
+		}
+
+		Ok(())
+	}
+
+	/// The environment the command starts with; nothing is inherited from the caller.
+	pub fn environment(&self) -> Vec<(OsString, OsString)> {
+		let mut vars = vec![
+			(OsString::from("ENCAGE_ROOT"), OsString::from("/")),
+			(OsString::from("PATH"), OsString::from(DEFAULT_PATH)),
+		];
+		if self.target.is_some() {
+			vars.push((OsString::from("ENCAGE_TARGET"), OsString::from(TARGET_PATH)));
+		}
+		if self.tty {
+			vars.push((OsString::from("TERM"), env::var_os("TERM").unwrap_or_else(|| OsString::from("xterm"))));
+		}
+
+		for (key, value) in &self.env {
+			vars.retain(|(k, _)| k != key);
+			vars.push((key.clone(), value.clone()));
+		}
+
+		vars
+	}
+}
+
+pub fn invalid<S: Into<String>>(s: S) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, s.into())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+	use super::{Limits, Mount, LayerMode};
+
+	#[test]
+	fn parse_size() {
+		assert_eq!(Limits::parse_size("4096"), Some(4096));
+		assert_eq!(Limits::parse_size("64k"), Some(64 << 10));
+		assert_eq!(Limits::parse_size("512M"), Some(512 << 20));
+		assert_eq!(Limits::parse_size("2G"), Some(2 << 30));
+		assert_eq!(Limits::parse_size(""), None);
+		assert_eq!(Limits::parse_size("G"), None);
+		assert_eq!(Limits::parse_size("1T"), None);
+		assert_eq!(Limits::parse_size("-1M"), None);
+		assert_eq!(Limits::parse_size("17179869184G"), None);
+	}
+
+	#[test]
+	fn parse_duration() {
+		assert_eq!(Limits::parse_duration("90"), Some(90));
+		assert_eq!(Limits::parse_duration("90s"), Some(90));
+		assert_eq!(Limits::parse_duration("5m"), Some(300));
+		assert_eq!(Limits::parse_duration("2h"), Some(7200));
+		assert_eq!(Limits::parse_duration("0"), None);
+		assert_eq!(Limits::parse_duration("m"), None);
+		assert_eq!(Limits::parse_duration("1d"), None);
+		assert_eq!(Limits::parse_duration("1.5h"), None);
+		assert_eq!(Limits::parse_duration("5000000000"), Some(5000000000));
+		assert_eq!(Limits::parse_duration("18446744073709551615h"), None);
+	}
+
+	#[test]
+	fn parse_mount() {
+		let mount = Mount::parse("/srv/cache:/var/cache").unwrap();
+		assert_eq!(mount.src, Path::new("/srv/cache"));
+		assert_eq!(mount.dest, Path::new("/var/cache"));
+
+		let mount = Mount::parse("a:b:c").unwrap();
+		assert_eq!(mount.src, Path::new("a"));
+		assert_eq!(mount.dest, Path::new("b:c"));
+
+		for invalid in &["", "/srv", ":/srv", "/srv:"] {
+			assert!(Mount::parse(invalid).is_err(), "{} parsed", invalid);
+		}
+	}
+
+	#[test]
+	fn parse_layer_mode() {
+		assert_eq!(LayerMode::parse("overlay"), Some(LayerMode::Overlay));
+		assert_eq!(LayerMode::parse("Copy"), None);
+	}
+}
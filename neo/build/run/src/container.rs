@@ -0,0 +1,227 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::{cmp, env, io, process};
+use libc;
+use config::{Config, Mode, TARGET_PATH};
+use sys;
+use user::{self, IdMapping};
+use layer::Layers;
+use mount::{self, bind, mount_fs, rootless};
+use dev;
+use init::SignalMask;
+use net::{self, Network};
+use cgroup::Cgroup;
+use caps;
+use seccomp;
+use pty::Pty;
+use etc::EtcFiles;
+
+/// Exit code used when the container could not be set up.
+pub const EXIT_SETUP: i32 = 125;
+/// Exit code used when the command exists but could not be run.
+pub const EXIT_EXEC: i32 = 126;
+/// Exit code used when the command could not be found.
+pub const EXIT_NOT_FOUND: i32 = 127;
+/// Exit code used when the command ran out of time, as with timeout(1).
+pub const EXIT_TIMEOUT: i32 = 124;
+
+/// Runs the configured command in a fresh container, returning its exit code.
+pub fn run(config: &Config) -> io::Result<i32> {
+	// Created while still owned by the caller, so it can be removed afterwards.
+	let cgroup = Cgroup::create(&config.limits)?;
+	let code = run_in(config, cgroup.as_ref());
+	match cgroup {
+		Some(cgroup) => code.and_then(|code| cgroup.remove().map(|_| code)),
+		None => code,
+	}
+}
+
+fn run_in(config: &Config, cgroup: Option<&Cgroup>) -> io::Result<i32> {
+	user::enter(config.ids)?;
+
+	let layers = match config.overlay {
+		Some(ref overlay) => Some(Layers::prepare(overlay, &config.root)?),
+		None => None,
+	};
+	let etc = EtcFiles::generate(config)?;
+
+	let mut pty = if config.tty && unsafe { libc::isatty(0) } == 1 {
+		Some(Pty::open()?)
+	} else {
+		None
+	};
+
+	// The child becomes pid 1 of a new pid namespace.
+	let signals = SignalMask::block()?;
+	let pid = sys::fork_into(libc::CLONE_NEWPID)?;
+	if pid == 0 {
+		let err = match cgroup.map(|c| c.join()).unwrap_or(Ok(())).and_then(|_| setup(config, layers.as_ref(), &etc, pty.as_ref())) {
+			Ok(()) => process::exit(init(config, &signals, pty.as_ref())),
+			Err(err) => err,
+		};
+		eprint(&err);
+		process::exit(EXIT_SETUP)
+	}
+
+	// Stay around until the container is gone, so interrupting encage-run
+	// still stops the command and cleans up after it.
+	if let Some(timeout) = config.limits.timeout {
+		unsafe { libc::alarm(cmp::min(timeout, libc::c_uint::MAX as u64) as libc::c_uint) };
+	}
+	let exit = match pty.as_mut() {
+		Some(pty) => pty.start(),
+		None => Ok(()),
+	}.and_then(|_| signals.supervise(pid, pty.as_ref()));
+	if let Some(pty) = pty {
+		pty.finish();
+	}
+	if exit.is_err() {
+		// Don't leave the container running while its root is taken apart.
+		unsafe { libc::kill(pid, libc::SIGKILL) };
+		let _ = sys::waitpid(pid);
+	}
+
+	// Cleaned up even if supervising failed, which is reported first.
+	let finished = match layers {
+		Some(layers) => layers.finish(),
+		None => Ok(()),
+	};
+	let removed = etc.remove(&config.root);
+	let exit = exit?;
+	finished?;
+	removed?;
+
+	if exit.timed_out {
+		eprint(&io::Error::new(io::ErrorKind::TimedOut, format!("time limit of {}s exceeded", config.limits.timeout.unwrap_or(0))));
+		return Ok(EXIT_TIMEOUT)
+	}
+
+	let code = sys::exit_code(exit.status);
+	if code != 0 {
+		if let Some(limit) = cgroup.and_then(|c| c.exceeded(&config.limits)) {
+			eprint(&io::Error::other(limit));
+		}
+	}
+
+	Ok(code)
+}
+
+/// Runs as pid 1 of the container: starts the command and supervises it,
+/// returning the exit code to leave with.
+fn init(config: &Config, signals: &SignalMask, pty: Option<&Pty>) -> i32 {
+	let pid = match sys::fork() {
+		Ok(0) => {
+			let attach = signals.restore().and_then(|_| pty.map(|pty| pty.attach()).unwrap_or(Ok(())));
+			if let Err(err) = attach.and_then(|_| confine(config)) {
+				eprint(&err);
+				process::exit(EXIT_SETUP)
+			}
+			let err = exec(config);
+			eprint(&err);
+			process::exit(if err.kind() == io::ErrorKind::NotFound { EXIT_NOT_FOUND } else { EXIT_EXEC })
+		},
+		Ok(pid) => pid,
+		Err(err) => {
+			eprint(&err);
+			return EXIT_SETUP
+		},
+	};
+
+	match signals.supervise(pid, None) {
+		Ok(exit) => sys::exit_code(exit.status),
+		Err(err) => {
+			eprint(&err);
+			EXIT_SETUP
+		},
+	}
+}
+
+/// Restricts what the command can do with its privileges, unless the
+/// container is privileged.
+fn confine(config: &Config) -> io::Result<()> {
+	if config.privileged {
+		return Ok(())
+	}
+
+	// The filter goes first, while the capability to install it without
+	// setting no_new_privs is still around.
+	seccomp::install()?;
+	let mut keep = caps::DEFAULT.to_vec();
+	keep.extend(&config.capabilities);
+	caps::restrict(&keep)
+}
+
+fn eprint(err: &io::Error) {
+	use std::io::Write;
+
+	let _ = writeln!(io::stderr(), "encage-run: {}", err);
+}
+
+/// Builds the container's mount namespace and pivots into it.
+fn setup(config: &Config, layers: Option<&Layers>, etc: &EtcFiles, pty: Option<&Pty>) -> io::Result<()> {
+	// The mount namespace is unshared here rather than before forking so the
+	// pivot below doesn't also move the parent's root.
+	let net = if config.network.isolated() { libc::CLONE_NEWNET } else { 0 };
+	sys::unshare(libc::CLONE_NEWNS | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS | net)?;
+	if config.network == Network::Loopback {
+		net::loopback_up()?;
+	}
+	sys::sethostname(&config.hostname)?;
+
+	// Keep every mount below from propagating back to the host.
+	sys::mount("none", "/", None, libc::MS_REC | libc::MS_PRIVATE, None)?;
+
+	let root = match layers {
+		Some(layers) => layers.mount()?.to_owned(),
+		None => {
+			// pivot_root requires the new root to be a mount point.
+			mount::context(sys::mount(&config.root, &config.root, None, libc::MS_BIND | libc::MS_REC, None), &config.root)?;
+			config.root.clone()
+		},
+	};
+
+	mount_fs(&root.join("proc"), "proc", libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, None)?;
+	if config.ids == IdMapping::Host {
+		mount_fs(&root.join("sys"), "sysfs", libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, None)?;
+	} else {
+		// A user namespace can't mount sysfs, so share the host's.
+		bind(Path::new("/sys"), &root.join("sys"))?;
+	}
+	mount_fs(&root.join("tmp"), "tmpfs", libc::MS_NOSUID | libc::MS_NODEV, None)?;
+	dev::populate(&root.join("dev"), &config.devices)?;
+	etc.mount(&root)?;
+	if let Some(pty) = pty {
+		bind(pty.path(), &root.join("dev/console"))?;
+	}
+
+	if let (Mode::Ocf, Some(target)) = (config.mode, config.target.as_ref()) {
+		bind(target, &root.join(rootless(Path::new(TARGET_PATH))))?;
+	}
+
+	for mount in &config.mounts {
+		bind(&mount.src, &root.join(rootless(&mount.dest)))?;
+	}
+
+	sys::chdir(&root)?;
+	sys::pivot_root(".", ".")?;
+	sys::umount(".", libc::MNT_DETACH)?;
+
+	sys::chdir(config.cwd.as_deref().unwrap_or(Path::new("/")))
+}
+
+fn exec(config: &Config) -> io::Error {
+	let args = match config.args.iter().map(sys::cstr).collect::<io::Result<Vec<_>>>() {
+		Ok(args) => args,
+		Err(e) => return e,
+	};
+
+	let vars: Vec<OsString> = env::vars_os().map(|(k, _)| k).collect();
+	for key in vars {
+		env::remove_var(key);
+	}
+	for (key, value) in config.environment() {
+		env::set_var(key, value);
+	}
+
+	sys::execvp(&args)
+}
@@ -0,0 +1,45 @@
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::io;
+use libc;
+use mount::{self, bind, mount_fs};
+
+/// Host devices every container gets.
+pub const DEFAULT_DEVICES: &[&str] = &["null", "zero", "full", "random", "urandom", "tty"];
+
+static LINKS: &[(&str, &str)] = &[
+	("fd", "/proc/self/fd"),
+	("stdin", "/proc/self/fd/0"),
+	("stdout", "/proc/self/fd/1"),
+	("stderr", "/proc/self/fd/2"),
+	("ptmx", "pts/ptmx"),
+];
+
+/// Builds a minimal `/dev` at `dev` on a fresh tmpfs.
+///
+/// Only the default devices and those in `extra` are bind mounted from the
+/// host. Terminals come from a private devpts instance, so the container can't
+/// reach the host's ptys.
+pub fn populate(dev: &Path, extra: &[PathBuf]) -> io::Result<()> {
+	mount_fs(dev, "tmpfs", libc::MS_NOSUID | libc::MS_NOEXEC, Some("mode=0755"))?;
+
+	for name in DEFAULT_DEVICES {
+		bind(&Path::new("/dev").join(name), &dev.join(name))?;
+	}
+
+	for device in extra {
+		let name = device.strip_prefix("/dev").unwrap_or(device);
+		bind(device, &dev.join(mount::rootless(name)))?;
+	}
+
+	mount_fs(&dev.join("pts"), "devpts", libc::MS_NOSUID | libc::MS_NOEXEC, Some("newinstance,ptmxmode=0666,mode=0620"))?;
+	mount_fs(&dev.join("shm"), "tmpfs", libc::MS_NOSUID | libc::MS_NODEV, Some("mode=1777"))?;
+	mount_fs(&dev.join("mqueue"), "mqueue", libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, None)?;
+
+	for &(name, target) in LINKS {
+		let link = dev.join(name);
+		mount::context(symlink(target, &link), &link)?;
+	}
+
+	Ok(())
+}
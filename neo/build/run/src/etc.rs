@@ -0,0 +1,115 @@
+//! Files under `/etc` that describe the container rather than the image.
+//!
+//! They're generated for every command and mounted over whatever the image
+//! has, so neither the host's configuration nor the bootstrap tarball's ends
+//! up baked into the image.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use config::Config;
+use mount::{self, bind};
+use net::Network;
+use sys;
+
+const FILES: &[&str] = &["hosts", "resolv.conf"];
+
+/// The generated files, and what has to be cleaned up after them.
+pub struct EtcFiles {
+	dir: PathBuf,
+	/// Paths below the root that will be created just to mount over.
+	placeholders: Vec<PathBuf>,
+}
+
+impl EtcFiles {
+	/// Writes the files for `config` to a temporary directory. Must be called
+	/// before the container root is assembled, to tell which files it lacks.
+	pub fn generate(config: &Config) -> io::Result<Self> {
+		let dir = sys::mkdtemp("encage-run-etc-")?;
+		match write_files(config, &dir) {
+			Ok(placeholders) => Ok(EtcFiles {
+				dir,
+				placeholders,
+			}),
+			Err(e) => {
+				let _ = fs::remove_dir_all(&dir);
+				Err(e)
+			},
+		}
+	}
+
+	/// Mounts the files over those of the container at `root`.
+	pub fn mount(&self, root: &Path) -> io::Result<()> {
+		for name in FILES {
+			let dest = root.join("etc").join(name);
+			// A symlink would be followed on the host rather than in the
+			// container, so leave those be.
+			if fs::symlink_metadata(&dest).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+				continue
+			}
+			bind(&self.dir.join(name), &dest)?;
+		}
+
+		Ok(())
+	}
+
+	/// Removes the generated files, along with any placeholders left in `root`
+	/// that are still empty.
+	pub fn remove(self, root: &Path) -> io::Result<()> {
+		for path in self.placeholders.iter().rev() {
+			let path = root.join(path);
+			let res = match fs::symlink_metadata(&path) {
+				Ok(ref meta) if meta.is_dir() => fs::remove_dir(&path),
+				Ok(ref meta) if meta.is_file() && meta.len() == 0 => fs::remove_file(&path),
+				_ => Ok(()),
+			};
+			match res {
+				// Something else was put in it meanwhile.
+				Err(ref e) if e.raw_os_error() == Some(::libc::ENOTEMPTY) => (),
+				res => mount::context(res, &path)?,
+			}
+		}
+
+		fs::remove_dir_all(&self.dir)
+	}
+}
+
+/// Writes the files to `dir`, returning the placeholders the root needs.
+fn write_files(config: &Config, dir: &Path) -> io::Result<Vec<PathBuf>> {
+	let hosts = format!("127.0.0.1\tlocalhost\n::1\tlocalhost\n127.0.1.1\t{}\n", config.hostname);
+
+	let resolv = if config.network == Network::Host {
+		let mut resolv = String::new();
+		mount::context(File::open("/etc/resolv.conf").and_then(|mut f| f.read_to_string(&mut resolv)), Path::new("/etc/resolv.conf"))?;
+		resolv
+	} else {
+		// Nothing to resolve names with, so fail right away rather than
+		// trying whatever name servers the image came with.
+		String::from("# no network\n")
+	};
+
+	for &(name, data) in &[("hosts", &hosts), ("resolv.conf", &resolv)] {
+		File::create(dir.join(name)).and_then(|mut f| f.write_all(data.as_bytes()))?;
+	}
+
+	// Files in any layer show up in the merged root, so only those missing
+	// from all of them get a placeholder.
+	let mut roots = vec![config.root.as_path()];
+	if let Some(ref overlay) = config.overlay {
+		roots.extend(overlay.lower.iter().map(|p| p.as_path()));
+	}
+	let exists = |path: &Path| roots.iter().any(|root| fs::symlink_metadata(root.join(path)).is_ok());
+
+	let mut placeholders = Vec::new();
+	if !exists(Path::new("etc")) {
+		placeholders.push(PathBuf::from("etc"));
+	}
+	for name in FILES {
+		let path = Path::new("etc").join(name);
+		if !exists(&path) {
+			placeholders.push(path);
+		}
+	}
+
+	Ok(placeholders)
+}
@@ -0,0 +1,106 @@
+use std::{io, mem, ptr};
+use libc;
+use pty::Pty;
+
+/// Signals passed on to the command instead of acting on them.
+pub const FORWARDED: &[libc::c_int] = &[
+	libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM,
+	libc::SIGUSR1, libc::SIGUSR2, libc::SIGWINCH,
+];
+
+/// How a supervised child ended.
+#[derive(Clone, Copy, Debug)]
+pub struct Exit {
+	/// The raw wait status.
+	pub status: libc::c_int,
+	/// Whether the child was killed because an `alarm` went off.
+	pub timed_out: bool,
+}
+
+/// The signals a supervisor waits on: everything forwarded, `SIGCHLD` and `SIGALRM`.
+pub struct SignalMask {
+	mask: libc::sigset_t,
+	previous: libc::sigset_t,
+}
+
+impl SignalMask {
+	/// Blocks the supervised signals so they queue up for `supervise`. Must be
+	/// called before forking the child, so no signal is lost in between.
+	pub fn block() -> io::Result<Self> {
+		unsafe {
+			let mut mask: libc::sigset_t = mem::zeroed();
+			let mut previous: libc::sigset_t = mem::zeroed();
+			libc::sigemptyset(&mut mask);
+			libc::sigaddset(&mut mask, libc::SIGCHLD);
+			libc::sigaddset(&mut mask, libc::SIGALRM);
+			for &signal in FORWARDED {
+				libc::sigaddset(&mut mask, signal);
+			}
+
+			if libc::sigprocmask(libc::SIG_BLOCK, &mask, &mut previous) < 0 {
+				return Err(io::Error::last_os_error())
+			}
+
+			Ok(SignalMask {
+				mask,
+				previous,
+			})
+		}
+	}
+
+	/// Restores the mask from before `block`, for the command about to be executed.
+	pub fn restore(&self) -> io::Result<()> {
+		if unsafe { libc::sigprocmask(libc::SIG_SETMASK, &self.previous, ptr::null_mut()) } < 0 {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Waits for `child` to exit.
+	///
+	/// Signals sent to this process are forwarded to `child`, and any other
+	/// process that exits meanwhile is reaped, which is the job of pid 1 once
+	/// orphans are reparented to it. Signals the terminal sends to the whole
+	/// foreground process group already reach the child directly, so only
+	/// those sent explicitly are forwarded. A pending `alarm` kills `child`
+	/// when it goes off, and resizing the terminal resizes `tty` to match.
+	pub fn supervise(&self, child: libc::pid_t, tty: Option<&Pty>) -> io::Result<Exit> {
+		let mut timed_out = false;
+		loop {
+			let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+			let signal = unsafe { libc::sigwaitinfo(&self.mask, &mut info) };
+			if signal < 0 {
+				let err = io::Error::last_os_error();
+				if err.kind() == io::ErrorKind::Interrupted {
+					continue
+				}
+				return Err(err)
+			}
+
+			if signal == libc::SIGCHLD {
+				loop {
+					let mut status = 0;
+					match unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) } {
+						pid if pid == child => return Ok(Exit {
+							status,
+							timed_out,
+						}),
+						pid if pid > 0 => (),
+						_ => break,
+					}
+				}
+			} else if signal == libc::SIGALRM && info.si_code > 0 {
+				timed_out = true;
+				unsafe { libc::kill(child, libc::SIGKILL) };
+			} else if signal == libc::SIGWINCH && info.si_code > 0 {
+				if let Some(tty) = tty {
+					tty.resize();
+				}
+			} else if info.si_code <= 0 {
+				// SI_USER, SI_QUEUE and SI_TKILL are all non-positive, unlike SI_KERNEL.
+				unsafe { libc::kill(child, signal) };
+			}
+		}
+	}
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+use std::{env, process};
+use libc;
+use config::{Overlay, LayerMode};
+use sys;
+
+/// A container root assembled from an image's own directory and the layers below it.
+///
+/// With overlayfs the layers are mounted read-only beneath the image, so
+/// writes only ever reach the image. Kernels that can't mount overlayfs
+/// (typically unprivileged, before Linux 5.11) instead get a scratch copy of
+/// every layer, and anything the command changes is copied back into the
+/// image afterwards.
+pub struct Layers<'a> {
+	overlay: &'a Overlay,
+	upper: &'a Path,
+	merged: PathBuf,
+	snapshot: Option<Snapshot>,
+}
+
+impl<'a> Layers<'a> {
+	/// Prepares the merged root for `upper`, copying the layers if overlayfs is
+	/// unavailable. Must be called outside the container's mount namespace.
+	pub fn prepare(overlay: &'a Overlay, upper: &'a Path) -> io::Result<Self> {
+		let mut layers = Layers {
+			overlay,
+			upper,
+			merged: sys::mkdtemp("encage-run-")?,
+			snapshot: None,
+		};
+
+		if let Err(e) = layers.populate() {
+			let _ = fs::remove_dir_all(&layers.merged);
+			return Err(e)
+		}
+
+		Ok(layers)
+	}
+
+	fn populate(&mut self) -> io::Result<()> {
+		let copy = match self.overlay.mode {
+			LayerMode::Overlay => false,
+			LayerMode::Copy => true,
+			LayerMode::Auto => !self.probe()?,
+		};
+
+		if copy {
+			for layer in self.overlay.lower.iter().rev().map(|p| p.as_path()).chain(Some(self.upper)) {
+				copy_tree(layer, &self.merged)?;
+			}
+			self.snapshot = Some(Snapshot::new(&self.merged)?);
+		}
+
+		Ok(())
+	}
+
+	/// Mounts the merged root, returning its path. Called inside the container's
+	/// private mount namespace.
+	pub fn mount(&self) -> io::Result<&Path> {
+		if self.snapshot.is_some() {
+			sys::mount(&self.merged, &self.merged, None, libc::MS_BIND | libc::MS_REC, None)?;
+		} else {
+			self.mount_overlay(&self.merged)?;
+		}
+
+		Ok(&self.merged)
+	}
+
+	fn mount_overlay(&self, target: &Path) -> io::Result<()> {
+		fs::create_dir_all(&self.overlay.work)?;
+		let lower = env::join_paths(&self.overlay.lower).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+		let options = format!("lowerdir={},upperdir={},workdir={}",
+			lower.to_string_lossy(), self.upper.display(), self.overlay.work.display()
+		);
+
+		sys::mount("overlay", target, Some("overlay"), 0, Some(&options))
+			.map_err(|e| io::Error::new(e.kind(), format!("overlay {}: {}", target.display(), e)))
+	}
+
+	/// Tries the overlay mount in a throwaway mount namespace.
+	fn probe(&self) -> io::Result<bool> {
+		let pid = sys::fork()?;
+		if pid == 0 {
+			let res = sys::unshare(libc::CLONE_NEWNS)
+				.and_then(|_| sys::mount("none", "/", None, libc::MS_REC | libc::MS_PRIVATE, None))
+				.and_then(|_| self.mount_overlay(&self.merged));
+			process::exit(if res.is_ok() { 0 } else { 1 })
+		}
+
+		sys::waitpid(pid).map(|status| sys::exit_code(status) == 0)
+	}
+
+	/// Copies the command's changes back into the image when the layers were
+	/// copied, and removes the merged root.
+	pub fn finish(self) -> io::Result<()> {
+		if let Some(ref snapshot) = self.snapshot {
+			snapshot.sync(&self.merged, self.upper, &self.overlay.lower)?;
+			fs::remove_dir_all(&self.merged)?;
+		} else {
+			fs::remove_dir(&self.merged)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Identifies a file's contents and attributes closely enough to tell whether a
+/// command touched it: any write, chmod or chown updates the ctime, and a
+/// replacement gets a new inode.
+#[derive(PartialEq, Eq)]
+struct Stamp {
+	ino: u64,
+	ctime: i64,
+	ctime_nsec: i64,
+}
+
+impl From<&Metadata> for Stamp {
+	fn from(meta: &Metadata) -> Self {
+		Stamp {
+			ino: meta.ino(),
+			ctime: meta.ctime(),
+			ctime_nsec: meta.ctime_nsec(),
+		}
+	}
+}
+
+struct Snapshot {
+	files: BTreeMap<PathBuf, Stamp>,
+}
+
+impl Snapshot {
+	fn new(root: &Path) -> io::Result<Self> {
+		let mut files = BTreeMap::new();
+		walk(root, Path::new(""), &mut |path, meta| {
+			files.insert(path.to_owned(), Stamp::from(meta));
+			Ok(())
+		})?;
+
+		Ok(Snapshot {
+			files,
+		})
+	}
+
+	fn sync(&self, merged: &Path, upper: &Path, lower: &[PathBuf]) -> io::Result<()> {
+		let mut seen = Vec::new();
+		walk(merged, Path::new(""), &mut |path, meta| {
+			if self.files.get(path) != Some(&Stamp::from(meta)) {
+				copy_entry(&merged.join(path), meta, &upper.join(path))?;
+			}
+			seen.push(path.to_owned());
+			Ok(())
+		})?;
+		seen.sort();
+
+		for path in self.files.keys().filter(|p| seen.binary_search(p).is_err()) {
+			let dest = upper.join(path);
+			match fs::symlink_metadata(&dest) {
+				Ok(ref meta) if meta.is_dir() => fs::remove_dir_all(&dest)?,
+				Ok(..) => fs::remove_file(&dest)?,
+				Err(..) => (),
+			}
+
+			if lower.iter().any(|l| fs::symlink_metadata(l.join(path)).is_ok()) {
+				let _ = writeln!(io::stderr(), "encage-run: warning: {} was removed, but layers can't record removals without overlayfs", path.display());
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Calls `f` with every entry below `root`, parents before their children.
+fn walk(root: &Path, path: &Path, f: &mut dyn FnMut(&Path, &Metadata) -> io::Result<()>) -> io::Result<()> {
+	for entry in fs::read_dir(root.join(path))? {
+		let entry = entry?;
+		let path = path.join(entry.file_name());
+		let meta = fs::symlink_metadata(root.join(&path))?;
+		f(&path, &meta)?;
+		if meta.is_dir() {
+			walk(root, &path, f)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Copies everything below `src` over `dest`, replacing existing entries.
+fn copy_tree(src: &Path, dest: &Path) -> io::Result<()> {
+	walk(src, Path::new(""), &mut |path, meta| copy_entry(&src.join(path), meta, &dest.join(path)))
+}
+
+/// Copies a single entry, without its children. Ownership is kept where the
+/// caller is allowed to set it.
+fn copy_entry(src: &Path, meta: &Metadata, dest: &Path) -> io::Result<()> {
+	let context = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", dest.display(), e));
+	let file_type = meta.file_type();
+
+	match fs::symlink_metadata(dest) {
+		Ok(ref existing) if existing.is_dir() && file_type.is_dir() => (),
+		Ok(ref existing) if existing.is_dir() => fs::remove_dir_all(dest).map_err(&context)?,
+		Ok(..) => fs::remove_file(dest).map_err(&context)?,
+		Err(..) => (),
+	}
+
+	if file_type.is_dir() {
+		fs::create_dir_all(dest).map_err(&context)?;
+		fs::set_permissions(dest, fs::Permissions::from_mode(meta.mode())).map_err(&context)?;
+	} else if file_type.is_symlink() {
+		fs::read_link(src).and_then(|target| symlink(target, dest)).map_err(&context)?;
+	} else if file_type.is_file() {
+		fs::copy(src, dest).map_err(&context)?;
+	} else {
+		let _ = writeln!(io::stderr(), "encage-run: warning: skipping special file {}", src.display());
+		return Ok(())
+	}
+
+	if let Ok(path) = sys::cstr(dest) {
+		unsafe { libc::lchown(path.as_ptr(), meta.uid(), meta.gid()) };
+	}
+	if file_type.is_file() {
+		// Setting the owner clears setuid and setgid bits, so restore the mode.
+		fs::set_permissions(dest, fs::Permissions::from_mode(meta.mode())).map_err(&context)?;
+	}
+
+	Ok(())
+}
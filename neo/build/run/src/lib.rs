@@ -0,0 +1,16 @@
+extern crate libc;
+
+pub mod caps;
+pub mod cgroup;
+pub mod config;
+pub mod container;
+pub mod dev;
+pub mod etc;
+pub mod init;
+pub mod layer;
+pub mod mount;
+pub mod net;
+pub mod pty;
+pub mod seccomp;
+pub mod sys;
+pub mod user;
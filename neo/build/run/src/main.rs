@@ -0,0 +1,219 @@
+extern crate encage_run as run;
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::io::{self, Write};
+use std::{env, process};
+use run::config::{self, Config, Mode, Mount, Overlay, LayerMode, Limits};
+use run::container;
+use run::caps;
+use run::user::IdMapping;
+use run::net::Network;
+
+const USAGE: &str = "\
+usage: encage-run exec [options] <root> <command> [args...]
+       encage-run ocf [options] <root> <command> [args...]
+
+exec runs the command inside the image at <root>. ocf runs it inside the
+container at <root>, with the image given by --target mounted at /mnt/target.
+
+options:
+    --target <dir>          the image being built (ocf only)
+    --cwd <dir>             working directory inside the container
+    --mount <src>:<dest>    bind mount a host path into the container
+    --env <name>=<value>    set an environment variable for the command
+    --device <path>         expose a host device, such as /dev/fuse, in
+                            addition to null, zero, full, random, urandom
+                            and tty
+    --network <network>     none (the default), loopback, or host to share
+                            the host's network
+    --hostname <name>       the container's hostname, also added to the
+                            generated /etc/hosts. Defaults to encage.
+    --lower <dir>           a read-only layer below the image, topmost first
+    --work <dir>            empty scratch directory for overlayfs, on the same
+                            filesystem as the image
+    --layers <mode>         how layers are combined: auto, overlay or copy
+    --ids <mapping>         how ids map onto the host: host, caller or
+                            subordinate. Defaults to host for root, otherwise
+                            subordinate if /etc/subuid and /etc/subgid have
+                            ranges for the caller, otherwise caller.
+    --memory <size>         memory limit, such as 512M or 2G
+    --cpus <cpus>           cpu limit as a number of cpus, such as 1.5
+    --pids <count>          limit on processes and threads
+    --timeout <duration>    wall-clock limit, such as 90s, 30m or 2h, after
+                            which the command is killed
+    --capability <name>     keep a capability, such as SYS_PTRACE, beyond
+                            the default set
+    --tty                   run the command on a pty when stdin is a
+                            terminal, for interactive use
+    --privileged            keep every capability and allow mount, kexec,
+                            module loading and the other syscalls that are
+                            otherwise blocked
+
+Commands get their own /etc/hosts and /etc/resolv.conf, mounted over those of
+the image for as long as they run. The name servers are the host's with
+--network host, and there are none otherwise.
+
+The memory, cpu and process limits are enforced by a cgroup v2 created below
+$ENCAGE_CGROUP, or below the cgroup encage-run runs in. It must be delegated
+to the caller and have the needed controllers enabled, or have no processes
+of its own so encage-run can enable them. Processes in $ENCAGE_CGROUP itself
+are moved into its encage-leaf child first.
+";
+
+fn parse_args<I: Iterator<Item=OsString>>(mut args: I) -> io::Result<Config> {
+	let mode = match args.next().as_ref().and_then(|m| m.to_str()) {
+		Some("exec") => Mode::Exec,
+		Some("ocf") => Mode::Ocf,
+		_ => return Err(config::invalid("expected a command")),
+	};
+
+	let mut target = None;
+	let mut cwd = None;
+	let mut mounts = Vec::new();
+	let mut env = Vec::new();
+	let mut ids = None;
+	let mut lower = Vec::new();
+	let mut work = None;
+	let mut layers = None;
+	let mut devices = Vec::new();
+	let mut network = None;
+	let mut limits = Limits::default();
+	let mut capabilities = Vec::new();
+	let mut privileged = false;
+	let mut tty = false;
+	let mut hostname = None;
+	let mut root = None;
+	while let Some(arg) = args.next() {
+		match arg.to_str() {
+			Some("--target") => target = Some(PathBuf::from(value(&mut args, &arg)?)),
+			Some("--cwd") => cwd = Some(PathBuf::from(value(&mut args, &arg)?)),
+			Some("--mount") => mounts.push(Mount::parse(value(&mut args, &arg)?)?),
+			Some("--env") => {
+				let var = value(&mut args, &arg)?;
+				let var = var.to_str().ok_or_else(|| config::invalid("--env must be valid utf-8"))?.to_owned();
+				let (key, value) = match var.find('=') {
+					Some(i) => (var[..i].to_owned(), var[i + 1..].to_owned()),
+					None => return Err(config::invalid(format!("invalid variable `{}`, expected name=value", var))),
+				};
+				env.push((OsString::from(key), OsString::from(value)));
+			},
+			Some("--device") => devices.push(PathBuf::from(value(&mut args, &arg)?)),
+			Some("--network") => {
+				let value = value(&mut args, &arg)?;
+				network = Some(value.to_str().and_then(Network::parse).ok_or_else(|| config::invalid(format!("unknown network {}", value.to_string_lossy())))?);
+			},
+			Some("--hostname") => {
+				let value = value(&mut args, &arg)?;
+				hostname = Some(value.into_string().map_err(|_| config::invalid("--hostname must be valid utf-8"))?);
+			},
+			Some("--lower") => lower.push(PathBuf::from(value(&mut args, &arg)?)),
+			Some("--work") => work = Some(PathBuf::from(value(&mut args, &arg)?)),
+			Some("--layers") => {
+				let value = value(&mut args, &arg)?;
+				layers = Some(value.to_str().and_then(LayerMode::parse).ok_or_else(|| config::invalid(format!("unknown layer mode {}", value.to_string_lossy())))?);
+			},
+			Some("--ids") => {
+				let value = value(&mut args, &arg)?;
+				ids = Some(value.to_str().and_then(IdMapping::parse).ok_or_else(|| config::invalid(format!("unknown id mapping {}", value.to_string_lossy())))?);
+			},
+			Some("--memory") => {
+				let value = value(&mut args, &arg)?;
+				limits.memory = Some(value.to_str().and_then(Limits::parse_size).ok_or_else(|| config::invalid(format!("invalid memory limit {}", value.to_string_lossy())))?);
+			},
+			Some("--cpus") => {
+				let value = value(&mut args, &arg)?;
+				limits.cpus = Some(value.to_str().and_then(|v| v.parse::<f64>().ok()).and_then(|v| if v > 0.0 { Some(v) } else { None })
+					.ok_or_else(|| config::invalid(format!("invalid cpu limit {}", value.to_string_lossy())))
+				?);
+			},
+			Some("--pids") => {
+				let value = value(&mut args, &arg)?;
+				limits.pids = Some(value.to_str().and_then(|v| v.parse().ok()).ok_or_else(|| config::invalid(format!("invalid process limit {}", value.to_string_lossy())))?);
+			},
+			Some("--timeout") => {
+				let value = value(&mut args, &arg)?;
+				limits.timeout = Some(value.to_str().and_then(Limits::parse_duration).ok_or_else(|| config::invalid(format!("invalid timeout {}", value.to_string_lossy())))?);
+			},
+			Some("--capability") => {
+				let value = value(&mut args, &arg)?;
+				capabilities.push(value.to_str().and_then(caps::from_str).ok_or_else(|| config::invalid(format!("unknown capability {}", value.to_string_lossy())))?);
+			},
+			Some("--privileged") => privileged = true,
+			Some("--tty") => tty = true,
+			Some("--") => {
+				root = args.next();
+				break
+			},
+			Some(s) if s.starts_with("--") => return Err(config::invalid(format!("unknown option {}", s))),
+			_ => {
+				root = Some(arg);
+				break
+			},
+		}
+	}
+	let root = PathBuf::from(root.ok_or_else(|| config::invalid("expected a root"))?);
+
+	let command: Vec<_> = args.collect();
+	if command.is_empty() {
+		return Err(config::invalid("expected a command to run"))
+	}
+	if mode == Mode::Ocf && target.is_none() {
+		return Err(config::invalid("ocf requires --target"))
+	}
+
+	let mut config = Config::new(mode, root, command);
+	config.target = target;
+	config.cwd = cwd;
+	config.env = env;
+	config.devices = devices;
+	config.limits = limits;
+	config.capabilities = capabilities;
+	config.privileged = privileged;
+	config.tty = tty;
+	if let Some(hostname) = hostname {
+		config.hostname = hostname;
+	}
+	if let Some(network) = network {
+		config.network = network;
+	}
+	if let Some(ids) = ids {
+		config.ids = ids;
+	}
+	config.apply_env()?;
+	config.mounts.extend(mounts);
+	if !lower.is_empty() {
+		config.overlay = Some(Overlay {
+			lower,
+			work: work.ok_or_else(|| config::invalid("--lower requires --work"))?,
+			mode: LayerMode::Auto,
+		});
+	}
+	if let (Some(overlay), Some(layers)) = (config.overlay.as_mut(), layers) {
+		overlay.mode = layers;
+	}
+
+	Ok(config)
+}
+
+fn value<I: Iterator<Item=OsString>>(args: &mut I, name: &OsString) -> io::Result<OsString> {
+	args.next().ok_or_else(|| config::invalid(format!("{} requires a value", name.to_string_lossy())))
+}
+
+fn main() {
+	let config = match parse_args(env::args_os().skip(1)) {
+		Ok(config) => config,
+		Err(e) => {
+			let _ = write!(io::stderr(), "encage-run: {}\n\n{}", e, USAGE);
+			process::exit(container::EXIT_SETUP)
+		},
+	};
+
+	match container::run(&config) {
+		Ok(code) => process::exit(code),
+		Err(e) => {
+			let _ = writeln!(io::stderr(), "encage-run: {}", e);
+			process::exit(container::EXIT_SETUP)
+		},
+	}
+}
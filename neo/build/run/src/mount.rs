@@ -0,0 +1,38 @@
+//! Mount helpers used while assembling the container root.
+
+use std::path::{Component, Path, PathBuf};
+use std::{fs, io};
+use libc;
+use sys;
+
+/// Mounts a fresh filesystem of type `fstype` at `dest`, creating it if needed.
+pub fn mount_fs(dest: &Path, fstype: &str, flags: libc::c_ulong, data: Option<&str>) -> io::Result<()> {
+	fs::create_dir_all(dest)?;
+	context(sys::mount(fstype, dest, Some(fstype), flags, data), dest)
+}
+
+/// Recursively bind mounts `src` at `dest`, creating a directory or empty
+/// file to mount over if needed.
+pub fn bind(src: &Path, dest: &Path) -> io::Result<()> {
+	let meta = context(fs::metadata(src), src)?;
+	if meta.is_dir() {
+		fs::create_dir_all(dest)?;
+	} else if fs::metadata(dest).is_err() {
+		if let Some(parent) = dest.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::File::create(dest)?;
+	}
+
+	context(sys::mount(src, dest, None, libc::MS_BIND | libc::MS_REC, None), dest)
+}
+
+/// Names the path an operation failed on.
+pub fn context<T>(res: io::Result<T>, path: &Path) -> io::Result<T> {
+	res.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// `path` relative to the root, for joining onto the container root.
+pub fn rootless(path: &Path) -> PathBuf {
+	path.components().filter(|c| matches!(*c, Component::Normal(..))).collect()
+}
@@ -0,0 +1,73 @@
+use std::{io, mem};
+use libc;
+
+/// The network a command can reach.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Default)]
+pub enum Network {
+	/// A private network namespace with no interfaces up.
+	#[default]
+ None,
+	/// A private network namespace with only the loopback interface up.
+	Loopback,
+	/// The host's network.
+	Host,
+}
+
+impl Network {
+	pub fn parse(s: &str) -> Option<Self> {
+		Some(match s {
+			"none" => Network::None,
+			"loopback" => Network::Loopback,
+			"host" => Network::Host,
+			_ => return None,
+		})
+	}
+
+	/// Whether the command needs its own network namespace.
+	pub fn isolated(&self) -> bool {
+		*self != Network::Host
+	}
+}
+
+
+const IF_NAMESIZE: usize = 16;
+const SIOCGIFFLAGS: libc::c_ulong = 0x8913;
+const SIOCSIFFLAGS: libc::c_ulong = 0x8914;
+const IFF_UP: libc::c_short = 0x1;
+
+#[repr(C)]
+struct IfReq {
+	name: [libc::c_char; IF_NAMESIZE],
+	flags: libc::c_short,
+	_pad: [u8; 22],
+}
+
+/// Brings up the loopback interface of the current network namespace.
+pub fn loopback_up() -> io::Result<()> {
+	unsafe {
+		let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+		if fd < 0 {
+			return Err(io::Error::last_os_error())
+		}
+
+		let mut req: IfReq = mem::zeroed();
+		for (dest, &c) in req.name.iter_mut().zip(b"lo") {
+			*dest = c as libc::c_char;
+		}
+
+		let mut res = libc::ioctl(fd, SIOCGIFFLAGS as _, &mut req);
+		if res >= 0 {
+			req.flags |= IFF_UP;
+			res = libc::ioctl(fd, SIOCSIFFLAGS as _, &mut req);
+		}
+		let err = io::Error::last_os_error();
+		libc::close(fd);
+
+		if res < 0 {
+			Err(io::Error::new(err.kind(), format!("lo: {}", err)))
+		} else {
+			Ok(())
+		}
+	}
+}
@@ -0,0 +1,141 @@
+//! Pseudo-terminals for interactive commands.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::mem;
+use libc;
+
+const TIOCGPTN: libc::c_ulong = 0x80045430;
+const TIOCSPTLCK: libc::c_ulong = 0x40045431;
+
+/// A pseudo-terminal whose slave end becomes the command's terminal, while
+/// its master end is relayed to and from the terminal encage-run runs in.
+pub struct Pty {
+	master: File,
+	slave: Option<File>,
+	path: PathBuf,
+	output: Option<JoinHandle<()>>,
+	termios: Option<libc::termios>,
+}
+
+impl Pty {
+	/// Opens a new pty from the host's devpts.
+	pub fn open() -> io::Result<Self> {
+		let master = OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOCTTY).open("/dev/ptmx")?;
+
+		let mut number: libc::c_uint = 0;
+		let unlock: libc::c_int = 0;
+		if unsafe { libc::ioctl(master.as_raw_fd(), TIOCSPTLCK as _, &unlock) } < 0 || unsafe { libc::ioctl(master.as_raw_fd(), TIOCGPTN as _, &mut number) } < 0 {
+			return Err(io::Error::last_os_error())
+		}
+
+		let path = PathBuf::from(format!("/dev/pts/{}", number));
+		let slave = OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOCTTY).open(&path)?;
+
+		Ok(Pty {
+			master,
+			slave: Some(slave),
+			path,
+			output: None,
+			termios: None,
+		})
+	}
+
+	/// The slave end, to expose as the container's console.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Makes the slave the controlling terminal and standard streams of the
+	/// calling process, which should be about to execute the command.
+	pub fn attach(&self) -> io::Result<()> {
+		let slave = self.slave.as_ref().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "pty is already relayed"))?.as_raw_fd();
+		unsafe {
+			if libc::setsid() < 0 || libc::ioctl(slave, libc::TIOCSCTTY as _, 0) < 0 {
+				return Err(io::Error::last_os_error())
+			}
+			for fd in 0..3 {
+				if libc::dup2(slave, fd) < 0 {
+					return Err(io::Error::last_os_error())
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Gives the pty the size of the terminal on stdin.
+	pub fn resize(&self) {
+		unsafe {
+			let mut size: libc::winsize = mem::zeroed();
+			if libc::ioctl(0, libc::TIOCGWINSZ as _, &mut size) == 0 {
+				libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ as _, &size);
+			}
+		}
+	}
+
+	/// Starts copying stdin to the command and its output to stdout, and
+	/// puts the terminal in raw mode so keys like `^C` reach the command
+	/// unchanged.
+	///
+	/// Must be called once the command has been forked, since the slave end
+	/// is closed here so the output ends along with the container, and the
+	/// container mustn't inherit a copy of the relay threads' locks.
+	pub fn start(&mut self) -> io::Result<()> {
+		self.slave = None;
+		self.resize();
+
+		// Nothing waits on input, which stops along with encage-run itself.
+		let input = self.master.try_clone()?;
+		thread::Builder::new().spawn(move || copy(io::stdin(), input))?;
+
+		let output = self.master.try_clone()?;
+		self.output = Some(thread::Builder::new().spawn(move || copy(output, io::stdout()))?);
+
+		unsafe {
+			let mut termios: libc::termios = mem::zeroed();
+			if libc::tcgetattr(0, &mut termios) == 0 {
+				let mut raw = termios;
+				libc::cfmakeraw(&mut raw);
+				if libc::tcsetattr(0, libc::TCSANOW, &raw) < 0 {
+					return Err(io::Error::last_os_error())
+				}
+				self.termios = Some(termios);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Waits for the remaining output and restores the terminal.
+	pub fn finish(self) {
+		if let Some(output) = self.output {
+			let _ = output.join();
+		}
+		if let Some(termios) = self.termios {
+			unsafe { libc::tcsetattr(0, libc::TCSANOW, &termios) };
+		}
+	}
+}
+
+/// Copies until either end is closed. Reading the master fails with `EIO`
+/// once every slave is closed, which also ends the copy.
+fn copy<R: Read, W: Write>(mut r: R, mut w: W) {
+	let mut buf = [0; 4096];
+	loop {
+		let n = match r.read(&mut buf) {
+			Ok(0) => return,
+			Ok(n) => n,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(..) => return,
+		};
+		// Prompts don't end with a newline, so flush everything right away.
+		if w.write_all(&buf[..n]).and_then(|_| w.flush()).is_err() {
+			return
+		}
+	}
+}
@@ -0,0 +1,267 @@
+use std::io;
+use libc;
+
+/// Syscalls commands can't make regardless of their capabilities: mounting,
+/// loading kernels and modules, rebooting, changing the clock, and entering
+/// or creating namespaces, which could otherwise get around the rest.
+///
+/// Syscalls added since Linux 5.0 have the same number on every
+/// architecture, so the new mount API is blocked by `BLOCKED_COMMON`.
+#[cfg(target_arch = "x86_64")]
+const BLOCKED: &[u32] = &[
+	103, // syslog
+	153, // vhangup
+	155, // pivot_root
+	159, // adjtimex
+	163, // acct
+	164, // settimeofday
+	165, // mount
+	166, // umount2
+	167, // swapon
+	168, // swapoff
+	169, // reboot
+	172, // iopl
+	173, // ioperm
+	175, // init_module
+	176, // delete_module
+	179, // quotactl
+	212, // lookup_dcookie
+	227, // clock_settime
+	246, // kexec_load
+	248, // add_key
+	249, // request_key
+	250, // keyctl
+	272, // unshare
+	298, // perf_event_open
+	304, // open_by_handle_at
+	305, // clock_adjtime
+	308, // setns
+	313, // finit_module
+	320, // kexec_file_load
+	321, // bpf
+	323, // userfaultfd
+];
+#[cfg(target_arch = "x86_64")]
+const SYS_CLONE: u32 = 56;
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000003e;
+
+#[cfg(target_arch = "aarch64")]
+const BLOCKED: &'static [u32] = &[
+	18, // lookup_dcookie
+	39, // umount2
+	40, // mount
+	41, // pivot_root
+	58, // vhangup
+	60, // quotactl
+	89, // acct
+	97, // unshare
+	104, // kexec_load
+	105, // init_module
+	106, // delete_module
+	112, // clock_settime
+	116, // syslog
+	142, // reboot
+	170, // settimeofday
+	171, // adjtimex
+	217, // add_key
+	218, // request_key
+	219, // keyctl
+	224, // swapon
+	225, // swapoff
+	241, // perf_event_open
+	265, // open_by_handle_at
+	266, // clock_adjtime
+	268, // setns
+	273, // finit_module
+	280, // bpf
+	282, // userfaultfd
+	294, // kexec_file_load
+];
+#[cfg(target_arch = "aarch64")]
+const SYS_CLONE: u32 = 220;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc00000b7;
+
+const BLOCKED_COMMON: &[u32] = &[
+	428, // open_tree
+	429, // move_mount
+	430, // fsopen
+	431, // fsconfig
+	432, // fsmount
+	433, // fspick
+	442, // mount_setattr
+];
+/// Fails with `ENOSYS` rather than `EPERM`, so libc falls back to `clone`,
+/// whose flags the filter can inspect. Those of `clone3` are behind a pointer.
+const SYS_CLONE3: u32 = 435;
+
+/// Namespace flags `clone` may not be called with, for the same reason `unshare` is blocked.
+const CLONE_NAMESPACES: u32 = 0x7e020000;
+/// Set in syscall numbers of the x32 ABI, which would otherwise slip past the checks.
+const X32_SYSCALL_BIT: u32 = 0x40000000;
+
+const PR_SET_SECCOMP: libc::c_int = 22;
+const SECCOMP_MODE_FILTER: libc::c_ulong = 2;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+const SECCOMP_RET_ERRNO: u32 = 0x00050000;
+
+const BPF_LD: u16 = 0x00;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_W: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+const BPF_K: u16 = 0x00;
+
+/// Offsets into `struct seccomp_data`.
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+/// The low half of the first argument, on little-endian architectures.
+const DATA_ARG0: u32 = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SockFilter {
+	code: u16,
+	jt: u8,
+	jf: u8,
+	k: u32,
+}
+
+#[repr(C)]
+struct SockFprog {
+	len: libc::c_ushort,
+	filter: *const SockFilter,
+}
+
+fn stmt(code: u16, k: u32) -> SockFilter {
+	SockFilter {
+		code,
+		jt: 0,
+		jf: 0,
+		k,
+	}
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+	SockFilter {
+		code,
+		jt,
+		jf,
+		k,
+	}
+}
+
+/// The default filter, under which blocked syscalls fail with `EPERM`.
+/// Syscalls made through another architecture's ABI are refused outright,
+/// since their numbers differ.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn filter() -> Vec<SockFilter> {
+	let deny = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+	let mut filter = vec![
+		stmt(BPF_LD | BPF_W | BPF_ABS, DATA_ARCH),
+		jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+		stmt(BPF_RET | BPF_K, deny),
+		stmt(BPF_LD | BPF_W | BPF_ABS, DATA_NR),
+		jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 0, 1),
+		stmt(BPF_RET | BPF_K, deny),
+		jump(BPF_JMP | BPF_JEQ | BPF_K, SYS_CLONE3, 0, 1),
+		stmt(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+	];
+	for &nr in BLOCKED.iter().chain(BLOCKED_COMMON) {
+		filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr, 0, 1));
+		filter.push(stmt(BPF_RET | BPF_K, deny));
+	}
+	filter.extend(&[
+		jump(BPF_JMP | BPF_JEQ | BPF_K, SYS_CLONE, 0, 3),
+		stmt(BPF_LD | BPF_W | BPF_ABS, DATA_ARG0),
+		jump(BPF_JMP | BPF_JSET | BPF_K, CLONE_NAMESPACES, 0, 1),
+		stmt(BPF_RET | BPF_K, deny),
+		stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+	]);
+	filter
+}
+
+/// Installs the default filter.
+///
+/// Requires `CAP_SYS_ADMIN`, so that `no_new_privs` doesn't have to be set
+/// and setuid programs keep working.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn install() -> io::Result<()> {
+	let filter = filter();
+	let prog = SockFprog {
+		len: filter.len() as libc::c_ushort,
+		filter: filter.as_ptr(),
+	};
+
+	if unsafe { libc::prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &prog as *const SockFprog as libc::c_ulong, 0, 0) } < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(())
+	}
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn install() -> io::Result<()> {
+	Err(io::Error::new(io::ErrorKind::Other, "seccomp filtering isn't supported on this architecture, run privileged instead"))
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tests {
+	use libc;
+	use super::*;
+
+	/// Runs the filter over a `struct seccomp_data`, returning its verdict.
+	fn run(filter: &[SockFilter], arch: u32, nr: u32, arg0: u32) -> u32 {
+		let mut acc = 0;
+		let mut pc = 0;
+		loop {
+			let insn = filter[pc];
+			pc += 1;
+			match insn.code {
+				c if c == BPF_LD | BPF_W | BPF_ABS => acc = match insn.k {
+					DATA_NR => nr,
+					DATA_ARCH => arch,
+					DATA_ARG0 => arg0,
+					k => panic!("load of unexpected offset {}", k),
+				},
+				c if c == BPF_RET | BPF_K => return insn.k,
+				c => {
+					let taken = match c & !BPF_JMP {
+						BPF_JEQ => acc == insn.k,
+						BPF_JGE => acc >= insn.k,
+						BPF_JSET => acc & insn.k != 0,
+						_ => panic!("unexpected instruction {:#x}", c),
+					};
+					pc += if taken { insn.jt } else { insn.jf } as usize;
+				},
+			}
+		}
+	}
+
+	#[test]
+	fn verdicts() {
+		let filter = filter();
+		assert!(filter.len() <= 4096);
+
+		let eperm = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+		let syscall = |nr, arg0| run(&filter, AUDIT_ARCH, nr, arg0);
+
+		assert_eq!(syscall(libc::SYS_read as u32, 0), SECCOMP_RET_ALLOW);
+		assert_eq!(syscall(libc::SYS_mount as u32, 0), eperm);
+		assert_eq!(syscall(libc::SYS_unshare as u32, 0), eperm);
+		for &nr in BLOCKED_COMMON {
+			assert_eq!(syscall(nr, 0), eperm, "syscall {} allowed", nr);
+		}
+
+		assert_eq!(syscall(SYS_CLONE3, 0), SECCOMP_RET_ERRNO | libc::ENOSYS as u32);
+		assert_eq!(syscall(SYS_CLONE, libc::SIGCHLD as u32), SECCOMP_RET_ALLOW);
+		assert_eq!(syscall(SYS_CLONE, (libc::CLONE_NEWUSER | libc::SIGCHLD) as u32), eperm);
+
+		assert_eq!(syscall(X32_SYSCALL_BIT | libc::SYS_read as u32, 0), eperm);
+		assert_eq!(run(&filter, 0x40000003, libc::SYS_read as u32, 0), eperm);
+	}
+}
@@ -0,0 +1,182 @@
+//! Thin wrappers over the libc calls the runtime needs, reporting failures as `io::Error`.
+
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::FromRawFd;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::{env, io, ptr};
+use libc;
+
+pub fn cstr<S: AsRef<OsStr>>(s: S) -> io::Result<CString> {
+	CString::new(s.as_ref().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+	if ret < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(ret)
+	}
+}
+
+pub fn unshare(flags: libc::c_int) -> io::Result<()> {
+	check(unsafe { libc::unshare(flags) }).map(|_| ())
+}
+
+pub fn sethostname(name: &str) -> io::Result<()> {
+	check(unsafe { libc::sethostname(name.as_ptr() as *const libc::c_char, name.len()) }).map(|_| ())
+}
+
+pub fn mount<S: AsRef<OsStr>, P: AsRef<Path>>(src: S, target: P, fstype: Option<&str>, flags: libc::c_ulong, data: Option<&str>) -> io::Result<()> {
+	let src = cstr(src)?;
+	let target = cstr(target.as_ref())?;
+	let fstype = match fstype {
+		Some(fstype) => Some(cstr(fstype)?),
+		None => None,
+	};
+	let data = match data {
+		Some(data) => Some(cstr(data)?),
+		None => None,
+	};
+
+	check(unsafe { libc::mount(
+		src.as_ptr(),
+		target.as_ptr(),
+		fstype.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null()),
+		flags,
+		data.as_ref().map(|s| s.as_ptr() as *const libc::c_void).unwrap_or(ptr::null())
+	) }).map(|_| ())
+}
+
+pub fn umount<P: AsRef<Path>>(target: P, flags: libc::c_int) -> io::Result<()> {
+	let target = cstr(target.as_ref())?;
+	check(unsafe { libc::umount2(target.as_ptr(), flags) }).map(|_| ())
+}
+
+pub fn pivot_root<P: AsRef<Path>, O: AsRef<Path>>(new_root: P, put_old: O) -> io::Result<()> {
+	let new_root = cstr(new_root.as_ref())?;
+	let put_old = cstr(put_old.as_ref())?;
+	check(unsafe { libc::syscall(libc::SYS_pivot_root, new_root.as_ptr(), put_old.as_ptr()) } as libc::c_int).map(|_| ())
+}
+
+pub fn chdir<P: AsRef<Path>>(path: P) -> io::Result<()> {
+	let path = cstr(path.as_ref())?;
+	check(unsafe { libc::chdir(path.as_ptr()) }).map(|_| ())
+}
+
+pub fn fork() -> io::Result<libc::pid_t> {
+	check(unsafe { libc::fork() })
+}
+
+/// Forks a child into the new namespaces in `flags`, like bubblewrap's
+/// `raw_clone`. Unlike unsharing them before forking, this leaves the
+/// caller in its own pid namespace, where it can still start threads.
+pub fn fork_into(flags: libc::c_int) -> io::Result<libc::pid_t> {
+	let flags = (flags | libc::SIGCHLD) as libc::c_ulong;
+	check(unsafe { libc::syscall(libc::SYS_clone, flags, 0 as libc::c_ulong, 0 as libc::c_ulong, 0 as libc::c_ulong, 0 as libc::c_ulong) } as libc::c_int)
+}
+
+/// Waits for `pid` to exit, returning its raw wait status.
+pub fn waitpid(pid: libc::pid_t) -> io::Result<libc::c_int> {
+	let mut status = 0;
+	loop {
+		match check(unsafe { libc::waitpid(pid, &mut status, 0) }) {
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e),
+			Ok(_) => return Ok(status),
+		}
+	}
+}
+
+/// The exit code a shell would report for a raw wait status.
+pub fn exit_code(status: libc::c_int) -> i32 {
+	let signal = status & 0x7f;
+	if signal == 0 {
+		(status >> 8) & 0xff
+	} else {
+		128 + signal
+	}
+}
+
+/// Replaces the current process, searching `$PATH` for the program. Only returns on failure.
+pub fn execvp(args: &[CString]) -> io::Error {
+	let mut argv: Vec<_> = args.iter().map(|s| s.as_ptr()).collect();
+	argv.push(ptr::null());
+
+	unsafe { libc::execvp(argv[0], argv.as_ptr()) };
+	io::Error::last_os_error()
+}
+
+pub fn getpid() -> libc::pid_t {
+	unsafe { libc::getpid() }
+}
+
+pub fn geteuid() -> libc::uid_t {
+	unsafe { libc::geteuid() }
+}
+
+pub fn getegid() -> libc::gid_t {
+	unsafe { libc::getegid() }
+}
+
+/// The login name of `uid`, if it has one.
+pub fn user_name(uid: libc::uid_t) -> Option<String> {
+	unsafe {
+		let passwd = libc::getpwuid(uid);
+		if passwd.is_null() {
+			None
+		} else {
+			Some(CStr::from_ptr((*passwd).pw_name).to_string_lossy().into_owned())
+		}
+	}
+}
+
+/// Creates a new directory in the temporary directory, named `prefix` followed
+/// by random characters and only accessible to the caller. Never reuses a
+/// directory that already exists, so nobody else can plant files in it.
+pub fn mkdtemp(prefix: &str) -> io::Result<PathBuf> {
+	let mut template = cstr(env::temp_dir().join(format!("{}XXXXXX", prefix)))?.into_bytes_with_nul();
+	if unsafe { libc::mkdtemp(template.as_mut_ptr() as *mut libc::c_char) }.is_null() {
+		return Err(io::Error::last_os_error())
+	}
+
+	template.pop();
+	Ok(PathBuf::from(OsString::from_vec(template)))
+}
+
+/// Creates a pipe, returning its read and write ends.
+pub fn pipe() -> io::Result<(File, File)> {
+	let mut fds = [0; 2];
+	check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+	Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::os::unix::fs::PermissionsExt;
+	use libc;
+	use super::{exit_code, mkdtemp};
+
+	#[test]
+	fn exit_codes() {
+		assert_eq!(exit_code(0), 0);
+		assert_eq!(exit_code(1 << 8), 1);
+		assert_eq!(exit_code(255 << 8), 255);
+		assert_eq!(exit_code(libc::SIGKILL), 128 + 9);
+		// Killed by a signal, with a core dump.
+		assert_eq!(exit_code(libc::SIGSEGV | 0x80), 128 + 11);
+	}
+
+	#[test]
+	fn private_temp_dirs() {
+		let first = mkdtemp("encage-run-test-").unwrap();
+		let second = mkdtemp("encage-run-test-").unwrap();
+		assert!(first != second);
+		assert_eq!(fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o700);
+
+		fs::remove_dir(&first).unwrap();
+		fs::remove_dir(&second).unwrap();
+	}
+}
@@ -0,0 +1,151 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write, BufRead, BufReader};
+use std::process;
+use libc;
+use sys;
+
+/// How user and group ids inside the container map onto the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdMapping {
+	/// Share the host's ids, without a user namespace. Requires root.
+	Host,
+	/// Map the caller to root, leaving every other id unmapped.
+	Caller,
+	/// Map the caller to root, and the caller's subordinate ids from
+	/// `/etc/subuid` and `/etc/subgid` to the ids from 1 upwards, so images
+	/// can contain files owned by other users.
+	Subordinate,
+}
+
+impl IdMapping {
+	pub fn parse(s: &str) -> Option<Self> {
+		Some(match s {
+			"host" => IdMapping::Host,
+			"caller" => IdMapping::Caller,
+			"subordinate" => IdMapping::Subordinate,
+			_ => return None,
+		})
+	}
+
+	/// Host ids for root, otherwise the widest mapping available to the caller.
+	pub fn detect() -> Self {
+		let uid = sys::geteuid();
+		if uid == 0 {
+			IdMapping::Host
+		} else {
+			let name = user_name(uid);
+			match (subordinate_ids(SUBUID_PATH, &name, uid), subordinate_ids(SUBGID_PATH, &name, uid)) {
+				(Ok(..), Ok(..)) => IdMapping::Subordinate,
+				_ => IdMapping::Caller,
+			}
+		}
+	}
+}
+
+pub const SUBUID_PATH: &str = "/etc/subuid";
+pub const SUBGID_PATH: &str = "/etc/subgid";
+
+/// A range of subordinate ids delegated to a user.
+#[derive(Clone, Copy, Debug)]
+pub struct SubIds {
+	pub start: u32,
+	pub count: u32,
+}
+
+/// Finds the first range in a subuid or subgid file belonging to the user `name` or `uid`.
+pub fn subordinate_ids(path: &str, name: &str, uid: libc::uid_t) -> io::Result<SubIds> {
+	let uid = uid.to_string();
+	let file = BufReader::new(File::open(path)?);
+	for line in file.lines() {
+		let line = line?;
+		let mut fields = line.trim().split(':');
+		match (fields.next(), fields.next().and_then(|s| s.parse().ok()), fields.next().and_then(|s| s.parse().ok())) {
+			(Some(owner), Some(start), Some(count)) if (owner == name || owner == uid) && count > 0 => return Ok(SubIds {
+				start,
+				count,
+			}),
+			_ => (),
+		}
+	}
+
+	Err(io::Error::new(io::ErrorKind::NotFound, format!("no subordinate ids for {} in {}", name, path)))
+}
+
+fn user_name(uid: libc::uid_t) -> String {
+	sys::user_name(uid).unwrap_or_else(|| uid.to_string())
+}
+
+/// Moves the current process into a new user namespace with the given mapping.
+///
+/// Afterwards the process is root inside the namespace, with the capabilities
+/// needed to set up the rest of the container.
+pub fn enter(mapping: IdMapping) -> io::Result<()> {
+	let uid = sys::geteuid();
+	let gid = sys::getegid();
+
+	match mapping {
+		IdMapping::Host => Ok(()),
+		IdMapping::Caller => {
+			sys::unshare(libc::CLONE_NEWUSER)?;
+			// An unprivileged process may only write gid_map once setgroups is denied.
+			write_file("/proc/self/setgroups", "deny")?;
+			write_file("/proc/self/uid_map", &format!("0 {} 1", uid))?;
+			write_file("/proc/self/gid_map", &format!("0 {} 1", gid))
+		},
+		IdMapping::Subordinate => {
+			let name = user_name(uid);
+			let subuid = subordinate_ids(SUBUID_PATH, &name, uid)?;
+			let subgid = subordinate_ids(SUBGID_PATH, &name, uid)?;
+			let pid = sys::getpid();
+
+			// Ranges can only be mapped by the setuid helpers from outside the
+			// new namespace, so fork one off before entering it.
+			let (mut read, mut write) = sys::pipe()?;
+			let helper = sys::fork()?;
+			if helper == 0 {
+				drop(write);
+				let mut ready = [0];
+				if let Ok(1) = read.read(&mut ready) {
+					let res = map_ids("newuidmap", pid, uid, subuid).and_then(|_| map_ids("newgidmap", pid, gid, subgid));
+					if let Err(e) = res {
+						let _ = writeln!(io::stderr(), "encage-run: {}", e);
+						process::exit(1)
+					}
+				}
+				process::exit(0)
+			}
+			drop(read);
+
+			let res = sys::unshare(libc::CLONE_NEWUSER).and_then(|_| write.write_all(&[1]));
+			drop(write);
+			let status = sys::waitpid(helper)?;
+			res?;
+			if sys::exit_code(status) == 0 {
+				Ok(())
+			} else {
+				Err(io::Error::other("failed to map subordinate ids"))
+			}
+		},
+	}
+}
+
+fn map_ids(helper: &str, pid: libc::pid_t, id: u32, sub: SubIds) -> io::Result<()> {
+	let status = process::Command::new(helper)
+		.arg(pid.to_string())
+		.args(["0", &id.to_string(), "1"])
+		.args(["1", &sub.start.to_string(), &sub.count.to_string()])
+		.status()
+		.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", helper, e)))
+	?;
+
+	if status.success() {
+		Ok(())
+	} else {
+		Err(io::Error::other(format!("{} failed with {}", helper, status)))
+	}
+}
+
+fn write_file(path: &str, data: &str) -> io::Result<()> {
+	OpenOptions::new().write(true).open(path).and_then(|mut f| f.write_all(data.as_bytes()))
+		.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
@@ -21,6 +21,26 @@ pub struct ImageRecipe {
 #[derive(Clone, Debug, Hash, Deserialize)]
 pub struct Image {
 	pub dest: String,
+	/// The `dest` directories of the images this one is built on top of,
+	/// nearest first. Image commands see them merged below `dest`.
+	#[serde(default)]
+	pub layers: Vec<String>,
+	/// Host devices exposed to commands, beyond the minimal default set.
+	#[serde(default)]
+	pub devices: Vec<String>,
+	/// The hostname image and ocf commands see, `encage` unless set.
+	#[serde(default)]
+	pub hostname: Option<String>,
+	/// Limits of every image and ocf command that doesn't set its own.
+	#[serde(default)]
+	pub limits: Limits,
+	/// Capabilities image and ocf commands keep beyond the runtime's default set.
+	#[serde(default)]
+	pub capabilities: Vec<String>,
+	/// Runs image and ocf commands with every capability and no syscall
+	/// filter, for bootstrap images that need to mount filesystems.
+	#[serde(default)]
+	pub privileged: bool,
 }
 
 #[derive(Clone, Debug, Hash, Deserialize)]
@@ -52,9 +72,93 @@ pub struct CommandCopy {
 pub struct CommandExec {
 	pub kind: CommandExecType,
 	pub cwd: Option<String>,
+	pub network: Network,
+	/// Overrides the limits of the image.
+	pub limits: Limits,
 	pub commands: Vec<CommandArgs>,
 }
 
+/// Resources a command may use, enforced by `encage-run`. Memory can be
+/// given in bytes or with a `K`, `M` or `G` suffix, and the timeout in
+/// seconds or with an `s`, `m` or `h` suffix.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Limits {
+	/// Memory in bytes.
+	pub memory: Option<u64>,
+	/// Cpu time in thousandths of a cpu.
+	pub millicpus: Option<u64>,
+	/// Number of processes and threads.
+	pub pids: Option<u64>,
+	/// Wall-clock time in seconds.
+	pub timeout: Option<u64>,
+}
+
+impl Limits {
+	/// These limits, falling back to `defaults` for any that are unset.
+	pub fn or(&self, defaults: &Limits) -> Limits {
+		Limits {
+			memory: self.memory.or(defaults.memory),
+			millicpus: self.millicpus.or(defaults.millicpus),
+			pids: self.pids.or(defaults.pids),
+			timeout: self.timeout.or(defaults.timeout),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		*self == Limits::default()
+	}
+
+	/// The `encage-run` options enforcing these limits.
+	pub fn args(&self) -> Vec<String> {
+		let mut args = Vec::new();
+		if let Some(memory) = self.memory {
+			args.push("--memory".into());
+			args.push(memory.to_string());
+		}
+		if let Some(millicpus) = self.millicpus {
+			args.push("--cpus".into());
+			args.push((millicpus as f64 / 1000.0).to_string());
+		}
+		if let Some(pids) = self.pids {
+			args.push("--pids".into());
+			args.push(pids.to_string());
+		}
+		if let Some(timeout) = self.timeout {
+			args.push("--timeout".into());
+			args.push(timeout.to_string());
+		}
+		args
+	}
+}
+
+/// The network a command can reach. Image and ocf commands default to none,
+/// so only steps that opt in can download anything.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Network {
+	None,
+	Loopback,
+	Host,
+}
+
+impl Network {
+	pub fn from_str(s: &str) -> Option<Self> {
+		Some(match s {
+			"none" => Network::None,
+			"loopback" => Network::Loopback,
+			"host" => Network::Host,
+			_ => return None,
+		})
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match *self {
+			Network::None => "none",
+			Network::Loopback => "loopback",
+			Network::Host => "host",
+		}
+	}
+}
+
 #[derive(Clone, Debug, Hash)]
 pub enum CommandArgs {
 	Shell(String),
@@ -97,6 +201,58 @@ impl serde::Deserialize for CommandArgs {
 	}
 }
 
+impl serde::Deserialize for Limits {
+	fn deserialize<D: serde::Deserializer>(d: &mut D) -> Result<Self, D::Error> {
+		fn scaled<E: Error>(value: Value, units: &[(&str, u64)], what: &str) -> Result<u64, E> {
+			let s = match value.clone().deserialize_into::<u64>() {
+				Ok(n) => n.to_string(),
+				Err(..) => try!(value.deserialize_into::<String>().map_err(DeserializerError::into_error)),
+			};
+
+			let n = {
+				let (digits, scale) = units.iter().find(|&&(suffix, _)| s.ends_with(suffix))
+					.map(|&(suffix, scale)| (&s[..s.len() - suffix.len()], scale))
+					.unwrap_or((&s[..], 1));
+				digits.parse::<u64>().ok().and_then(|n| n.checked_mul(scale))
+			};
+
+			match n {
+				Some(n) if n > 0 => Ok(n),
+				_ => Err(E::invalid_value(&format!("invalid {} `{}`", what, s))),
+			}
+		}
+
+		StringMap::deserialize(d).and_then(|mut v| {
+			let limits = Limits {
+				memory: match v.remove("memory") {
+					Some(memory) => Some(try!(scaled(memory, &[("K", 1 << 10), ("M", 1 << 20), ("G", 1 << 30)], "memory limit"))),
+					None => None,
+				},
+				millicpus: match v.remove("cpus") {
+					Some(cpus) => match try!(cpus.deserialize_into::<f64>().map_err(DeserializerError::into_error)) {
+						cpus if cpus > 0.0 => Some((cpus * 1000.0) as u64),
+						_ => return Err(D::Error::invalid_value("cpus must be positive")),
+					},
+					None => None,
+				},
+				pids: match v.remove("pids") {
+					Some(pids) => Some(try!(scaled(pids, &[], "process limit"))),
+					None => None,
+				},
+				timeout: match v.remove("timeout") {
+					Some(timeout) => Some(try!(scaled(timeout, &[("s", 1), ("m", 60), ("h", 60 * 60)], "timeout"))),
+					None => None,
+				},
+			};
+
+			match v.keys().next() {
+				Some(key) => Err(D::Error::unknown_field(key)),
+				None => Ok(limits),
+			}
+		})
+	}
+}
+
 impl serde::Deserialize for Command {
 	fn deserialize<D: serde::Deserializer>(d: &mut D) -> Result<Self, D::Error> {
 		#[derive(Deserialize)]
@@ -106,6 +262,10 @@ impl serde::Deserialize for Command {
 			#[serde(default)]
 			root: Option<String>,
 			#[serde(default)]
+			network: Option<String>,
+			#[serde(default)]
+			limits: Limits,
+			#[serde(default)]
 			command: Option<CommandArgs>,
 			#[serde(default)]
 			commands: Vec<CommandArgs>,
@@ -128,6 +288,17 @@ impl serde::Deserialize for Command {
 							_ => unreachable!(),
 						},
 						cwd: v.cwd,
+						network: match (&kind[..], v.network.as_ref().map(|n| Network::from_str(n))) {
+							(_, Some(None)) => return Err(D::Error::invalid_value("network must be none, loopback or host")),
+							("host", Some(Some(network))) if network != Network::Host => return Err(D::Error::invalid_value("host commands always use the host network")),
+							("host", _) => Network::Host,
+							(_, Some(Some(network))) => network,
+							(_, None) => Network::None,
+						},
+						limits: match &kind[..] {
+							"host" if !v.limits.is_empty() => return Err(D::Error::invalid_value("resource limits are only enforced in containers")),
+							_ => v.limits,
+						},
 						commands: {
 							let commands: Vec<_> = v.command.into_iter().chain(v.commands.into_iter()).collect();
 							if commands.len() == 0 {
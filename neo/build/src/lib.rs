@@ -64,31 +64,82 @@ impl<'a> dag::ToShellString for CommandContext<'a> {
 				shell_string(&["install", "-Dm", &mode[..], &copy.src[..], &dest[..]])
 			},
 			schema::Command::Exec(ref exec) => {
-				use std::iter::once;
-
 				exec.commands.iter().map(|command| {
 					let args: Vec<&str> = match *command {
-						schema::CommandArgs::Shell(ref s) => ["sh", "-ec", &s[..]].iter().map(|&s| s).collect(),
-						schema::CommandArgs::Exec { ref process, ref args } => once(&process[..]).chain(args.iter().map(|s| &s[..])).collect(),
+						schema::CommandArgs::Shell(ref s) => vec!["sh", "-ec", &s[..]],
+						schema::CommandArgs::Exec { ref process, ref args } => Some(&process[..]).into_iter().chain(args.iter().map(|s| &s[..])).collect(),
 					};
 
-					match exec.kind {
-						schema::CommandExecType::Ocf { ref root } => {
-							shell_string(["encage-run", "ocf", &root[..]].iter().map(|&s| s).chain(args))
-						},
-						schema::CommandExecType::Image => {
-							shell_string(["encage-run", "exec", &self.image.image.dest[..]].iter().map(|&s| s).chain(args))
-						},
-						schema::CommandExecType::Host => {
-							shell_string(args)
-						},
-					}
+					shell_string(exec_args(&self.image.image, exec, &args, &RunOptions::default()))
 				}).fold(String::new(), |s, c| if s.len() == 0 { s } else { s + " && " } + &c)
 			},
 		}
 	}
 }
 
+/// How to run a command outside of the build itself.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions<'a> {
+	/// Attaches the command to a pty, when run from a terminal.
+	pub tty: bool,
+	/// Runs image commands on a throwaway layer at this directory, above the
+	/// image and its layers, so anything they change is discarded.
+	pub scratch: Option<&'a str>,
+}
+
+/// The arguments that run `args` the way `exec` runs its commands: through
+/// `encage-run` for image and ocf commands, or as they are for host commands.
+pub fn exec_args(image: &schema::Image, exec: &schema::CommandExec, args: &[&str], run: &RunOptions) -> Vec<String> {
+	let mut options: Vec<String> = Vec::new();
+	if run.tty {
+		options.push("--tty".into());
+	}
+	if let Some(ref cwd) = exec.cwd {
+		options.extend(vec!["--cwd".into(), cwd.clone()]);
+	}
+	if exec.network != schema::Network::None {
+		options.extend(vec!["--network".into(), exec.network.as_str().into()]);
+	}
+	if let Some(ref hostname) = image.hostname {
+		options.extend(vec!["--hostname".into(), hostname.clone()]);
+	}
+	for device in &image.devices {
+		options.extend(vec!["--device".into(), device.clone()]);
+	}
+	options.extend(exec.limits.or(&image.limits).args());
+	for capability in &image.capabilities {
+		options.extend(vec!["--capability".into(), capability.clone()]);
+	}
+	if image.privileged {
+		options.push("--privileged".into());
+	}
+
+	let (runtime, root): (Vec<String>, &str) = match exec.kind {
+		schema::CommandExecType::Ocf { ref root } => {
+			(vec!["encage-run".into(), "ocf".into(), "--target".into(), image.dest.clone()], &root[..])
+		},
+		schema::CommandExecType::Image => {
+			let (root, lower) = match run.scratch {
+				Some(scratch) => (scratch, Some(&image.dest).into_iter().chain(&image.layers).collect::<Vec<_>>()),
+				None => (&image.dest[..], image.layers.iter().collect()),
+			};
+			for layer in &lower {
+				options.extend(vec!["--lower".into(), (*layer).clone()]);
+			}
+			if !lower.is_empty() {
+				options.extend(vec!["--work".into(), format!("{}.work", root.trim_right_matches('/'))]);
+			}
+
+			(vec!["encage-run".into(), "exec".into()], root)
+		},
+		schema::CommandExecType::Host => {
+			return args.iter().map(|&s| s.to_owned()).collect()
+		},
+	};
+
+	runtime.into_iter().chain(options).chain(Some(root.to_owned())).chain(args.iter().map(|&s| s.to_owned())).collect()
+}
+
 pub struct Stamper<T> {
 	inner: T,
 	stamp: Stamp,